@@ -18,6 +18,9 @@ use tokio::sync::{Semaphore, RwLock};
 use dashmap::DashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use vxug_scraper::http::{self, HttpConfig};
+use vxug_scraper::journal::{self, Journal, Listing};
+
 #[derive(Clone)]
 struct AdvancedScraper {
     client: Arc<Client>,
@@ -27,6 +30,7 @@ struct AdvancedScraper {
     stats: Arc<ScrapingStats>,
     regex_cache: Arc<RwLock<dashmap::DashMap<String, Regex>>>,
     selector_cache: Arc<RwLock<dashmap::DashMap<String, Selector>>>,
+    journal: Option<Arc<Journal>>,
 }
 
 #[derive(Default)]
@@ -71,17 +75,16 @@ struct DownloadTask {
 }
 
 impl AdvancedScraper {
-    fn new(max_concurrent_downloads: usize, max_concurrent_scrapes: usize) -> Self {
-        let client = Arc::new(
-            Client::builder()
-                .timeout(Duration::from_secs(30))
-                .tcp_keepalive(Duration::from_secs(60))
-                .pool_max_idle_per_host(20)
-                .pool_idle_timeout(Duration::from_secs(90))
-                .user_agent("Mozilla/5.0 (compatible; VX-Underground-Scraper/1.0)")
-                .build()
-                .expect("Failed to create HTTP client")
-        );
+    fn new(
+        max_concurrent_downloads: usize,
+        max_concurrent_scrapes: usize,
+        http_config: &HttpConfig,
+        journal: Option<Journal>,
+    ) -> Self {
+        let client = Arc::new(http::build_client(http_config, None, None).unwrap_or_else(|e| {
+            eprintln!("Error: failed to set up HTTP client: {}", e);
+            std::process::exit(1);
+        }));
 
         Self {
             client,
@@ -91,6 +94,7 @@ impl AdvancedScraper {
             stats: Arc::new(ScrapingStats::default()),
             regex_cache: Arc::new(RwLock::new(DashMap::new())),
             selector_cache: Arc::new(RwLock::new(DashMap::new())),
+            journal: journal.map(Arc::new),
         }
     }
 
@@ -157,7 +161,7 @@ impl AdvancedScraper {
 
             self.stats.increment_pages();
             
-            if self.stats.pages_scraped.load(Ordering::Relaxed) % 10 == 0 {
+            if self.stats.pages_scraped.load(Ordering::Relaxed).is_multiple_of(10) {
                 self.stats.print_stats();
             }
         }
@@ -173,23 +177,31 @@ impl AdvancedScraper {
     ) -> Result<(Vec<(String, String)>, Vec<DownloadTask>), Box<dyn std::error::Error + Send + Sync>> {
         println!("Processing: {} -> {}", url, dir);
 
-        let response = self.client.get(url).send().await?.text().await?;
-        let document = Html::parse_document(&response);
+        let listing = match self.journal.as_ref().and_then(|j| j.listing(url)) {
+            Some(listing) => listing,
+            None => {
+                let response = self.client.get(url).send().await?.text().await?;
+                let listing = self.parse_listing(&response).await;
+                if let Some(journal) = &self.journal {
+                    journal.record_listing(url, &listing);
+                }
+                listing
+            }
+        };
 
         let mut subdirectories = Vec::new();
         let mut downloads = Vec::new();
 
-        let file_selector = self.get_cached_selector(r#"a[href$=".pdf"], a[href$=".zip"], a[href$=".7z"], a[href$=".rar"]"#).await;
         let sanitize_regex = self.get_cached_regex(r"[<>:/\\|?*]").await;
 
         // extract links sequentially
-        let links: Vec<(String, String)> = document
-            .select(&file_selector)
-            .filter_map(|link| {
-                let href = link.value().attr("href")?.to_string();
-                let name = href.split('/').last()?.to_string();
+        let links: Vec<(String, String)> = listing
+            .files
+            .iter()
+            .filter_map(|href| {
+                let name = href.split('/').next_back()?.to_string();
                 let sanitized_name = sanitize_regex.replace_all(&name, "_").to_string();
-                Some((sanitized_name, href))
+                Some((sanitized_name, href.clone()))
             })
             .collect();
 
@@ -212,10 +224,9 @@ impl AdvancedScraper {
             }
         } else {
 
-            let category_selector = self.get_cached_selector(r#"div.cursor-pointer span.text-white.text-xs.truncate"#).await;
-            let categories: Vec<String> = document
-                .select(&category_selector)
-                .map(|e| e.inner_html().trim().to_string())
+            let categories: Vec<String> = listing
+                .dirs
+                .into_iter()
                 .filter(|category| !self.visited_urls.contains_key(category))
                 .collect();
 
@@ -235,6 +246,25 @@ impl AdvancedScraper {
         Ok((subdirectories, downloads))
     }
 
+    /// Extract file links, or subdirectory names when there are no files, from a page
+    async fn parse_listing(&self, html: &str) -> Listing {
+        let document = Html::parse_document(html);
+        let file_selector = self.get_cached_selector(r#"a[href$=".pdf"], a[href$=".zip"], a[href$=".7z"], a[href$=".rar"]"#).await;
+        let files: Vec<String> = document
+            .select(&file_selector)
+            .filter_map(|link| link.value().attr("href").map(str::to_string))
+            .collect();
+        if !files.is_empty() {
+            return Listing { files, ..Default::default() };
+        }
+        let category_selector = self.get_cached_selector(r#"div.cursor-pointer span.text-white.text-xs.truncate"#).await;
+        let dirs = document
+            .select(&category_selector)
+            .map(|e| e.inner_html().trim().to_string())
+            .collect();
+        Listing { dirs, ..Default::default() }
+    }
+
     async fn process_downloads_immediately(&self, downloads: Vec<DownloadTask>) {
         println!("Starting immediate download of {} files", downloads.len());
         
@@ -256,6 +286,15 @@ impl AdvancedScraper {
     }
 
     async fn download_with_retry(&self, task: DownloadTask) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(journal) = &self.journal {
+            if journal.completed(&task.url) && std::path::Path::new(&task.file_path).exists() {
+                println!("Completed in the journal, skipping: {}", task.file_path);
+                return Ok(());
+            }
+            if journal.failed(&task.url) {
+                println!("Retrying {}: failed in the previous run", task.file_name);
+            }
+        }
         let _permit = self.download_semaphore.acquire().await?;
         
         println!("Downloading: {} -> {}", task.file_name, task.file_path);
@@ -264,6 +303,9 @@ impl AdvancedScraper {
             match self.download_file(&task.url, &task.file_path).await {
                 Ok(bytes_downloaded) => {
                     println!("[+] Downloaded: {} ({} bytes)", task.file_name, bytes_downloaded);
+                    if let Some(journal) = &self.journal {
+                        journal.record_completed(&task.url, &task.file_path);
+                    }
                     self.stats.increment_files();
                     self.stats.add_bytes(bytes_downloaded);
                     return Ok(());
@@ -274,6 +316,9 @@ impl AdvancedScraper {
                         sleep(Duration::from_millis(500 * attempt as u64)).await;
                     } else {
                         self.stats.increment_errors();
+                        if let Some(journal) = &self.journal {
+                            journal.record_failed(&task.url, &task.file_path);
+                        }
                     }
                 }
             }
@@ -325,24 +370,78 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let start_url = "https://vx-underground.org/";
     let root_dir = "Downloads";
 
+    // same HTTP options as the main scraper (--timeout, --user-agent, --header, ...)
+    let args: Vec<String> = std::env::args().collect();
+    let mut http_config = HttpConfig::default();
+    let mut resume = false;
+    let mut fresh = false;
+    let mut i = 1;
+    while i < args.len() {
+        if args[i] == "--resume" {
+            resume = true;
+            i += 1;
+            continue;
+        }
+        if args[i] == "--fresh" {
+            fresh = true;
+            i += 1;
+            continue;
+        }
+        if !http::FLAGS.contains(&args[i].as_str()) || i + 1 >= args.len() {
+            eprintln!("Error: unsupported argument: {}", args[i]);
+            std::process::exit(1);
+        }
+        if let Err(e) = http::apply_flag(&mut http_config, &args[i], &args[i + 1]) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        i += 2;
+    }
+
+    if !resume && !fresh && journal::unfinished(root_dir) {
+        eprintln!(
+            "Error: {}/{} belongs to an interrupted run; pass --resume to continue it or --fresh to start over",
+            root_dir,
+            journal::JOURNAL_FILE
+        );
+        std::process::exit(1);
+    }
+
     banner();
     
     println!("Configuration:");
     println!("  • Max concurrent downloads: 12");
     println!("  • Max concurrent page scrapes: 8");
     println!("  • Download batch size: 20");
-    println!("  • HTTP timeout: 30s");
+    println!("  • HTTP timeouts: connect {:?}, read {:?}, total {:?}", http_config.connect_timeout, http_config.read_timeout, http_config.total_timeout);
     println!("  • Features: BFS traversal, caching, concurrent processing");
     println!();
     println!("Press Enter to start =>");
     
     let _ = std::io::stdin().read_line(&mut String::new())?;
 
-    let scraper = AdvancedScraper::new(12, 8); 
+    fs::create_dir_all(root_dir)?;
+    let journal = match Journal::open(root_dir, resume) {
+        Ok(journal) => {
+            if resume {
+                let (listings, completed, failed) = journal.counts();
+                println!("Resuming from journal: {} listings, {} completed and {} failed files", listings, completed, failed);
+            }
+            Some(journal)
+        }
+        Err(e) => {
+            eprintln!("Failed to open crawl journal: {}", e);
+            None
+        }
+    };
+    let scraper = AdvancedScraper::new(12, 8, &http_config, journal);
     
     let start_time = std::time::Instant::now();
     scraper.scrape_with_bfs(base_url, start_url, root_dir).await?;
     let elapsed = start_time.elapsed();
+    if let Some(journal) = &scraper.journal {
+        journal.finish();
+    }
 
     println!("Scraping completed!");
     println!("Total time: {:?}", elapsed);
@@ -0,0 +1,307 @@
+/*
+    Archive inspection for password-protected sample archives.
+    ZIP and 7z files are opened in place with a password list (default
+    "infected") and their entries listed with sizes, CRCs and the digests of
+    the decompressed data. Nothing is written to disk; the listing is stored
+    in the manifest.
+*/
+
+use crate::hash::{Hasher, Hashes};
+use serde::{Deserialize, Serialize};
+use sevenz_rust::{Password, SevenZReader};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+
+/// Password tried when no list is configured
+pub const DEFAULT_PASSWORD: &str = "infected";
+
+/// List archive contents after each download (--inspect)
+pub static INSPECT: AtomicBool = AtomicBool::new(false);
+/// Decompressed bytes hashed per archive before the rest is only listed
+const MAX_HASH_BYTES: u64 = 4 << 30;
+/// Decompressed bytes read from one entry to test a password
+const PROBE_BYTES: u64 = 64 << 10;
+static PASSWORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Archive formats that can be opened
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Zip,
+    #[serde(rename = "7z")]
+    SevenZip,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        let ext = name.rsplit_once('.')?.1.to_ascii_lowercase();
+        match ext.as_str() {
+            "zip" => Some(Format::Zip),
+            "7z" => Some(Format::SevenZip),
+            _ => None,
+        }
+    }
+}
+
+/// One entry of an archive
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compressed_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crc32: Option<u32>,
+    #[serde(default)]
+    pub is_dir: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none", flatten)]
+    pub hashes: Option<Hashes>,
+}
+
+/// Result of inspecting one archive
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchiveListing {
+    pub format: Format,
+    pub encrypted: bool,
+    /// Password from the list that opened the archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    pub entries: Vec<ArchiveEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Add a password to try, in order
+pub fn add_password(password: &str) {
+    PASSWORDS.lock().unwrap().push(password.to_string());
+}
+
+/// Read passwords from a file, one per line
+pub fn load_password_file(path: &str) -> io::Result<usize> {
+    let content = std::fs::read_to_string(path)?;
+    let mut count = 0;
+    for line in content.lines().filter(|l| !l.is_empty()) {
+        add_password(line);
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Configured passwords, or the default list
+pub fn passwords() -> Vec<String> {
+    let list = PASSWORDS.lock().unwrap();
+    if list.is_empty() { vec![DEFAULT_PASSWORD.to_string()] } else { list.clone() }
+}
+
+fn zip_entry(file: &zip::read::ZipFile<'_, File>) -> ArchiveEntry {
+    ArchiveEntry {
+        name: file.name().to_string(),
+        size: file.size(),
+        compressed_size: Some(file.compressed_size()),
+        crc32: Some(file.crc32()),
+        is_dir: file.is_dir(),
+        hashes: None,
+    }
+}
+
+fn sevenz_entry(entry: &sevenz_rust::SevenZArchiveEntry) -> ArchiveEntry {
+    ArchiveEntry {
+        name: entry.name.clone(),
+        size: entry.size,
+        compressed_size: None,
+        crc32: entry.has_crc.then_some(entry.crc as u32),
+        is_dir: entry.is_directory,
+        hashes: None,
+    }
+}
+
+/// Reader that fails once more than `remaining` bytes have been read through
+/// it, so a declared entry size is never what bounds decompression
+struct Limited<'a, R> {
+    inner: R,
+    remaining: &'a mut u64,
+    limit: u64,
+}
+
+impl<R: Read> Read for Limited<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n as u64 > *self.remaining {
+            return Err(io::Error::other(format!("more than {} bytes of decompressed data", self.limit)));
+        }
+        *self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Find which password opens the archive: Ok(None) when none is needed.
+/// Only the first PROBE_BYTES of an entry are decoded per password; an entry
+/// that small is read to the end, where its CRC is checked.
+fn find_password(path: &Path, format: Format, passwords: &[String]) -> Result<Option<String>, String> {
+    match format {
+        Format::Zip => {
+            let mut zip = zip::ZipArchive::new(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            // the smallest encrypted entry is the cheapest to test a password against
+            let mut smallest: Option<(usize, u64)> = None;
+            for i in 0..zip.len() {
+                let file = zip.by_index_raw(i).map_err(|e| e.to_string())?;
+                if file.encrypted() && !file.is_dir() && smallest.is_none_or(|(_, size)| file.size() < size) {
+                    smallest = Some((i, file.size()));
+                }
+            }
+            let Some((index, _)) = smallest else {
+                return Ok(None);
+            };
+            for password in passwords {
+                // ZipCrypto accepts 1 in 256 wrong passwords up front; decoding data rejects most of the rest
+                if let Ok(file) = zip.by_index_decrypt(index, password.as_bytes())
+                    && io::copy(&mut file.take(PROBE_BYTES), &mut io::sink()).is_ok()
+                {
+                    return Ok(Some(password.clone()));
+                }
+            }
+            Err("no password in the list opens the archive".to_string())
+        }
+        Format::SevenZip => {
+            let candidates = std::iter::once(None).chain(passwords.iter().map(Some));
+            for password in candidates {
+                let pw = password.map(|p| Password::from(p.as_str())).unwrap_or_else(Password::empty);
+                let Ok(mut reader) = SevenZReader::open(path, pw) else {
+                    continue;
+                };
+                // decoding the first entry with data is enough to tell a wrong password
+                let result = reader.for_each_entries(|entry, data| {
+                    if entry.size == 0 {
+                        return Ok(true);
+                    }
+                    io::copy(&mut data.take(PROBE_BYTES), &mut io::sink())?;
+                    Ok(false)
+                });
+                if result.is_ok() {
+                    return Ok(password.cloned());
+                }
+            }
+            Err("no password in the list opens the archive".to_string())
+        }
+    }
+}
+
+/// Open an archive with the first working password and call `visit` with each
+/// entry and a reader over its decompressed data, until it returns Ok(false).
+/// Everything decompressed, including what is skipped, counts against
+/// `max_bytes` and the archive fails once it is exceeded.
+/// Returns the password that was used.
+pub fn for_each_entry(
+    path: &Path,
+    format: Format,
+    passwords: &[String],
+    max_bytes: u64,
+    mut visit: impl FnMut(&ArchiveEntry, &mut dyn Read) -> io::Result<bool>,
+) -> Result<Option<String>, String> {
+    let password = find_password(path, format, passwords)?;
+    let mut remaining = max_bytes;
+    match format {
+        Format::Zip => {
+            let mut zip = zip::ZipArchive::new(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            for i in 0..zip.len() {
+                let mut file = match &password {
+                    Some(pw) => zip.by_index_decrypt(i, pw.as_bytes()),
+                    None => zip.by_index(i),
+                }
+                .map_err(|e| e.to_string())?;
+                let entry = zip_entry(&file);
+                let mut data = Limited { inner: &mut file, remaining: &mut remaining, limit: max_bytes };
+                if !visit(&entry, &mut data).map_err(|e| format!("{}: {}", entry.name, e))? {
+                    break;
+                }
+            }
+        }
+        Format::SevenZip => {
+            let pw = password.as_deref().map(Password::from).unwrap_or_else(Password::empty);
+            let mut reader = SevenZReader::open(path, pw).map_err(|e| e.to_string())?;
+            reader
+                .for_each_entries(|entry, data| {
+                    let entry = sevenz_entry(entry);
+                    let mut data = Limited { inner: data, remaining: &mut remaining, limit: max_bytes };
+                    let go_on = visit(&entry, &mut data)?;
+                    // entries of a solid block are decoded in sequence, so skip what was not read
+                    if go_on {
+                        io::copy(&mut data, &mut io::sink())?;
+                    }
+                    Ok(go_on)
+                })
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(password)
+}
+
+/// List the entries of an archive without decompressing anything
+fn list_entries(path: &Path, format: Format, password: Option<&str>) -> Result<Vec<ArchiveEntry>, String> {
+    match format {
+        Format::Zip => {
+            let mut zip = zip::ZipArchive::new(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            (0..zip.len())
+                .map(|i| zip.by_index_raw(i).map(|f| zip_entry(&f)).map_err(|e| e.to_string()))
+                .collect()
+        }
+        Format::SevenZip => {
+            let pw = password.map(Password::from).unwrap_or_else(Password::empty);
+            let archive = sevenz_rust::Archive::open_with_password(path, &pw).map_err(|e| e.to_string())?;
+            Ok(archive.files.iter().map(sevenz_entry).collect())
+        }
+    }
+}
+
+/// Inspect a downloaded archive: list its entries and hash their contents
+/// within the MAX_HASH_BYTES budget
+pub fn inspect(path: &Path, format: Format) -> ArchiveListing {
+    let passwords = passwords();
+    let mut listing = ArchiveListing { format, encrypted: false, password: None, entries: Vec::new(), error: None };
+    let mut budget = MAX_HASH_BYTES;
+    let mut hashed: HashMap<String, Hashes> = HashMap::new();
+    // the visitor stops at the budget itself, one byte past it is never drained
+    let result = for_each_entry(path, format, &passwords, MAX_HASH_BYTES + 1, |entry, data| {
+        if entry.is_dir {
+            return Ok(true);
+        }
+        if entry.size > budget {
+            return Ok(false);
+        }
+        // the declared size is not trusted either: an entry that runs past the budget is not recorded
+        let mut hasher = Hasher::new();
+        let read = hasher.update_reader(data.take(budget + 1))?;
+        if read > budget {
+            return Ok(false);
+        }
+        budget -= read;
+        hashed.insert(entry.name.clone(), hasher.finalize());
+        Ok(true)
+    });
+    match result {
+        Ok(password) => {
+            listing.encrypted = password.is_some();
+            listing.password = password;
+        }
+        Err(e) => {
+            listing.encrypted = e.contains("password");
+            listing.error = Some(e);
+        }
+    }
+    // names and sizes are readable even when the contents are not (except encrypted 7z headers)
+    match list_entries(path, format, listing.password.as_deref()) {
+        Ok(mut entries) => {
+            for entry in &mut entries {
+                entry.hashes = hashed.remove(&entry.name);
+            }
+            listing.entries = entries;
+        }
+        Err(e) if listing.error.is_none() => listing.error = Some(e),
+        Err(_) => {}
+    }
+    listing
+}
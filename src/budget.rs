@@ -0,0 +1,167 @@
+/*
+    Disk budget (--budget).
+    The tree is measured first, as for --estimate. Files already complete on
+    disk are kept and count against the budget; the others are taken in the
+    order of the selection strategy (--select) for as long as they fit. What
+    was left out, and why, is reported and written to `budget-left-out.tsv`.
+*/
+
+use crate::catalog::human_size;
+use crate::estimate::{self, RemoteFile};
+use percent_encoding::percent_decode_str;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::AtomicU64;
+
+/// Files the budget did not cover, written to the output directory
+pub const LEFT_OUT_FILE: &str = "budget-left-out.tsv";
+
+/// Most bytes the mirror may hold (--budget, 0 = no budget)
+pub static BUDGET: AtomicU64 = AtomicU64::new(0);
+/// Order in which files are chosen (--select)
+pub static SELECT: OnceLock<Selection> = OnceLock::new();
+/// Collections in priority order for --select priority (--priority), as paths on the site
+pub static PRIORITY: OnceLock<Vec<String>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    Newest,
+    Smallest,
+    Priority,
+}
+
+impl Selection {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "newest" => Some(Selection::Newest),
+            "smallest" => Some(Selection::Smallest),
+            "priority" => Some(Selection::Priority),
+            _ => None,
+        }
+    }
+}
+
+/// Files to fetch and files left out, with the reason
+pub struct Plan {
+    pub selected: Vec<RemoteFile>,
+    pub left_out: Vec<(RemoteFile, &'static str)>,
+    /// Bytes of complete files already on disk
+    pub kept: u64,
+    /// Bytes the selected files will take once downloaded
+    pub to_fetch: u64,
+}
+
+/// Decoded directories of a file's URL on the site. Local names may have
+/// been sanitized or tagged, so --priority is matched against these.
+fn remote_dirs(url: &str) -> Vec<String> {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return Vec::new();
+    };
+    let mut segments: Vec<String> = url
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .filter(|s| !s.is_empty())
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+        .collect();
+    segments.pop();
+    segments
+}
+
+/// Position of the first --priority collection containing `file`; unlisted ones come last
+fn priority(file: &RemoteFile) -> usize {
+    let Some(collections) = PRIORITY.get() else {
+        return 0;
+    };
+    let parts = remote_dirs(&file.url);
+    collections
+        .iter()
+        .position(|collection| {
+            let prefix: Vec<&str> = collection.split('/').filter(|s| !s.is_empty()).collect();
+            parts.len() >= prefix.len() && parts.iter().zip(&prefix).all(|(a, b)| a == b)
+        })
+        .unwrap_or(collections.len())
+}
+
+/// Choose the files that fit in `budget` bytes
+pub fn select(files: Vec<RemoteFile>, budget: u64, selection: Selection) -> Plan {
+    let (mut selected, mut candidates): (Vec<RemoteFile>, Vec<RemoteFile>) = files.into_iter().partition(|f| f.complete);
+    let kept: u64 = selected.iter().map(|f| f.present).sum();
+    // listing order is not stable across runs, so ties are broken by path
+    candidates.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+    match selection {
+        Selection::Newest => candidates.sort_by_key(|f| std::cmp::Reverse(f.modified)),
+        Selection::Smallest => candidates.sort_by_key(|f| f.size.unwrap_or(u64::MAX)),
+        Selection::Priority => candidates.sort_by_cached_key(priority),
+    }
+    let mut used = kept;
+    let mut to_fetch = 0;
+    let mut left_out = Vec::new();
+    for file in candidates {
+        match file.size {
+            None => left_out.push((file, "unknown size")),
+            Some(size) if used.saturating_add(size) <= budget => {
+                used += size;
+                to_fetch += size;
+                selected.push(file);
+            }
+            Some(_) => left_out.push((file, "over budget")),
+        }
+    }
+    Plan { selected, left_out, kept, to_fetch }
+}
+
+impl Plan {
+    /// Print what fits and what was left out, per collection
+    pub fn print_report(&self, root_dir: &str, budget: u64) {
+        let size = |bytes: u64| human_size(bytes as i64);
+        let present = self.selected.iter().filter(|f| f.complete).count();
+        println!("=== Budget ===");
+        println!("Budget: {}", size(budget));
+        println!("Already present: {} in {} files", size(self.kept), present);
+        println!("Selected to download: {} in {} files", size(self.to_fetch), self.selected.len() - present);
+        if self.kept > budget {
+            eprintln!("[!] Files already present exceed the budget by {}; nothing new is downloaded", size(self.kept - budget));
+        }
+        if self.left_out.is_empty() {
+            println!("Left out: nothing, everything fits");
+            return;
+        }
+        let left_bytes: u64 = self.left_out.iter().filter_map(|(f, _)| f.size).sum();
+        println!("Left out: {} in {} files", size(left_bytes), self.left_out.len());
+        let mut collections: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+        for (file, _) in &self.left_out {
+            let parts = estimate::relative_dir(root_dir, &file.dir);
+            let entry = collections.entry(parts.first().cloned().unwrap_or_default()).or_default();
+            entry.0 += 1;
+            entry.1 += file.size.unwrap_or(0);
+        }
+        for (collection, (files, bytes)) in &collections {
+            println!("  {}: {} files, {}", if collection.is_empty() { "." } else { collection }, files, size(*bytes));
+        }
+        let unknown = self.left_out.iter().filter(|(_, reason)| *reason == "unknown size").count();
+        if unknown > 0 {
+            println!("  ({} of them of unknown size)", unknown);
+        }
+    }
+
+    /// Write `url<TAB>local path<TAB>size<TAB>reason` for each file left out
+    pub fn save_left_out(&self, root_dir: &str) -> io::Result<()> {
+        let path = Path::new(root_dir).join(LEFT_OUT_FILE);
+        if self.left_out.is_empty() {
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let mut out = Vec::new();
+        for (file, reason) in &self.left_out {
+            let size = file.size.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string());
+            writeln!(out, "{}\t{}\t{}\t{}", file.url, file.file_path, size, reason)?;
+        }
+        fs::write(path, out)
+    }
+}
@@ -0,0 +1,471 @@
+/*
+    SQLite catalog of remote collections and local files.
+    Every directory and file the crawl discovers is recorded with its remote
+    URL, local path, size, timestamps, download status and hashes, and kept
+    up to date as the crawl runs. `vxug-scraper catalog` queries it.
+    Writes go to a dedicated thread that owns the connection, so SQLite I/O
+    never blocks the async workers.
+*/
+
+use crate::error;
+use crate::hash::Hashes;
+use crate::manifest::relative_key;
+use chrono::Utc;
+use rusqlite::types::{ToSqlOutput, Value};
+use rusqlite::{Connection, OptionalExtension, ToSql, params, params_from_iter};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+/// Default catalog name inside the output directory
+pub const CATALOG_FILE: &str = "catalog.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS directories (
+    url         TEXT PRIMARY KEY,
+    parent_url  TEXT,
+    local_path  TEXT NOT NULL,
+    status      TEXT NOT NULL,
+    file_count  INTEGER NOT NULL DEFAULT 0,
+    first_seen  TEXT NOT NULL,
+    last_seen   TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS files (
+    url           TEXT PRIMARY KEY,
+    directory_url TEXT,
+    name          TEXT NOT NULL,
+    local_path    TEXT NOT NULL,
+    size          INTEGER,
+    status        TEXT NOT NULL,
+    first_seen    TEXT NOT NULL,
+    last_seen     TEXT NOT NULL,
+    updated_at    TEXT NOT NULL,
+    downloaded_at TEXT,
+    sha256        TEXT,
+    sha1          TEXT,
+    md5           TEXT
+);
+CREATE TABLE IF NOT EXISTS locations (
+    local_path  TEXT PRIMARY KEY,
+    sha256      TEXT NOT NULL,
+    url         TEXT NOT NULL,
+    kind        TEXT NOT NULL,
+    linked_to   TEXT,
+    recorded_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS locations_sha256 ON locations(sha256);
+CREATE INDEX IF NOT EXISTS files_directory ON files(directory_url);
+CREATE INDEX IF NOT EXISTS files_status ON files(status);
+";
+
+/// Download status of a catalogued file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileStatus {
+    /// Listed on the site, not downloaded yet
+    Discovered,
+    /// Present locally and complete
+    Complete,
+    /// Failed during the crawl and queued for a retry round
+    Deferred,
+    /// Stopped by a shutdown, partial data kept for resuming
+    Partial,
+    /// Still failing at the end of the run
+    Failed,
+}
+
+impl FileStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileStatus::Discovered => "discovered",
+            FileStatus::Complete => "complete",
+            FileStatus::Deferred => "deferred",
+            FileStatus::Partial => "partial",
+            FileStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Work run on the writer thread against the connection
+type Job = Box<dyn FnOnce(&mut Connection) + Send>;
+
+pub struct Catalog {
+    jobs: Sender<Job>,
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+impl Catalog {
+    /// Open (or create) the catalog database at `path`
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.execute_batch(SCHEMA)?;
+        let (jobs, queue) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            let mut conn = conn;
+            for job in queue {
+                job(&mut conn);
+            }
+        });
+        Ok(Self { jobs })
+    }
+
+    /// Run `job` on the writer thread and wait for its result
+    fn call<T: Send + 'static>(&self, job: impl FnOnce(&mut Connection) -> T + Send + 'static) -> T {
+        let (reply, result) = mpsc::channel();
+        let job: Job = Box::new(move |conn| {
+            let _ = reply.send(job(conn));
+        });
+        self.jobs.send(job).ok().and_then(|_| result.recv().ok()).expect("catalog writer thread stopped")
+    }
+
+    /// Queue a write; it runs after every write queued before it
+    fn execute(&self, sql: &'static str, params: &[&dyn ToSql]) {
+        let values: Vec<Value> = params
+            .iter()
+            .map(|p| match p.to_sql() {
+                Ok(ToSqlOutput::Borrowed(v)) => v.into(),
+                Ok(ToSqlOutput::Owned(v)) => v,
+                _ => Value::Null,
+            })
+            .collect();
+        let job: Job = Box::new(move |conn| {
+            if let Err(e) = conn.execute(sql, params_from_iter(values)) {
+                eprintln!("[!] Failed to update {}: {}", CATALOG_FILE, e);
+            }
+        });
+        if self.jobs.send(job).is_err() {
+            eprintln!("[!] Failed to update {}: catalog writer thread stopped", CATALOG_FILE);
+        }
+    }
+
+    /// Wait until every queued write has reached the database
+    pub fn flush(&self) {
+        self.call(|_| ());
+    }
+
+    /// Record a directory page and the number of files it lists
+    pub fn record_directory(&self, url: &str, parent_url: Option<&str>, local_path: &str, status: &str, file_count: usize) {
+        let now = now();
+        self.execute(
+            "INSERT INTO directories (url, parent_url, local_path, status, file_count, first_seen, last_seen)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6)
+             ON CONFLICT(url) DO UPDATE SET
+                parent_url = COALESCE(excluded.parent_url, parent_url),
+                local_path = excluded.local_path,
+                status = excluded.status,
+                file_count = CASE WHEN excluded.status = 'listed' THEN excluded.file_count ELSE file_count END,
+                last_seen = excluded.last_seen",
+            params![url, parent_url, local_path, status, file_count as i64, now],
+        );
+    }
+
+    /// Record a file found in a listing; a known file keeps its status
+    pub fn record_file(&self, url: &str, directory_url: &str, name: &str, local_path: &str) {
+        let now = now();
+        self.execute(
+            "INSERT INTO files (url, directory_url, name, local_path, status, first_seen, last_seen, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?6)
+             ON CONFLICT(url) DO UPDATE SET
+                directory_url = excluded.directory_url,
+                name = excluded.name,
+                local_path = excluded.local_path,
+                last_seen = excluded.last_seen",
+            params![url, directory_url, name, local_path, FileStatus::Discovered.as_str(), now],
+        );
+    }
+
+    /// Update the status of a file, creating the row if a retry list introduced it
+    pub fn set_status(&self, url: &str, local_path: &str, status: FileStatus) {
+        let now = now();
+        let name = Path::new(local_path).file_name().and_then(|n| n.to_str()).unwrap_or("");
+        self.execute(
+            "INSERT INTO files (url, name, local_path, status, first_seen, last_seen, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?5, ?5)
+             ON CONFLICT(url) DO UPDATE SET status = excluded.status, updated_at = excluded.updated_at",
+            params![url, name, local_path, status.as_str(), now],
+        );
+    }
+
+    /// Mark a file complete with its size on disk; `downloaded` is false for files already present
+    pub fn set_complete(&self, url: &str, local_path: &str, size: u64, downloaded: bool) {
+        self.set_status(url, local_path, FileStatus::Complete);
+        let now = now();
+        self.execute(
+            "UPDATE files SET size = ?2, local_path = ?3,
+                downloaded_at = CASE WHEN ?4 THEN ?5 ELSE downloaded_at END
+             WHERE url = ?1",
+            params![url, size as i64, local_path, downloaded, now],
+        );
+    }
+
+    /// Store the digests of a completed file
+    pub fn set_hashes(&self, url: &str, hashes: &Hashes) {
+        self.execute(
+            "UPDATE files SET sha256 = ?2, sha1 = ?3, md5 = ?4 WHERE url = ?1",
+            params![url, hashes.sha256, hashes.sha1, hashes.md5],
+        );
+    }
+
+    /// Record a place where a piece of content appears. `kind` is `file` for a
+    /// full copy, or the dedup link type when it points at `linked_to`.
+    pub fn record_location(&self, sha256: &str, local_path: &str, url: &str, kind: &str, linked_to: Option<&str>) {
+        self.execute(
+            "INSERT INTO locations (local_path, sha256, url, kind, linked_to, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(local_path) DO UPDATE SET
+                sha256 = excluded.sha256, url = excluded.url, kind = excluded.kind,
+                linked_to = excluded.linked_to, recorded_at = excluded.recorded_at",
+            params![local_path, sha256, url, kind, linked_to, now()],
+        );
+    }
+
+    /// Point files and locations recorded at the old paths of `renamed` at the
+    /// new names. Paths are compared relative to `root`, the way the crawl
+    /// recorded them is kept. Returns the number of rows changed.
+    pub fn rename_paths(&self, root: &Path, renamed: &[(PathBuf, PathBuf)]) -> rusqlite::Result<usize> {
+        let root = root.to_path_buf();
+        let renamed = renamed.to_vec();
+        self.call(move |conn| rename_paths(conn, &root, &renamed))
+    }
+}
+
+fn rename_paths(conn: &mut Connection, root: &Path, renamed: &[(PathBuf, PathBuf)]) -> rusqlite::Result<usize> {
+    let new_names: HashMap<String, &OsStr> = renamed
+        .iter()
+        .filter_map(|(old, new)| Some((relative_key(root, old), new.file_name()?)))
+        .collect();
+    let renamed_path = |stored: &str| {
+        let name = new_names.get(&relative_key(root, Path::new(stored)))?;
+        Some(Path::new(stored).with_file_name(name).to_string_lossy().into_owned())
+    };
+    let tx = conn.transaction()?;
+    let mut changed = 0;
+    for (table, column) in [("files", "local_path"), ("locations", "local_path"), ("locations", "linked_to")] {
+        let stored: Vec<String> = tx
+            .prepare(&format!("SELECT DISTINCT {column} FROM {table} WHERE {column} IS NOT NULL"))?
+            .query_map([], |r| r.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for old in stored {
+            if let Some(new) = renamed_path(&old) {
+                changed += tx.execute(&format!("UPDATE {table} SET {column} = ?2 WHERE {column} = ?1"), params![old, new])?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(changed)
+}
+
+fn print_command_help() {
+    println!("Usage:");
+    println!("  vxug-scraper catalog [OPTIONS] [summary|files|dirs|dups|find <TEXT>|show <URL|PATH>]");
+    println!();
+    println!("Options:");
+    println!("  -o, --output-dir <DIR>         Output directory holding {} (default: Downloads)", CATALOG_FILE);
+    println!("  --catalog-file <FILE>          Catalog database to query");
+    println!("  --status <STATUS>              Only list files with this status");
+    println!("                                 (discovered, complete, deferred, partial, failed)");
+    println!("  --limit <NUM>                  Maximum rows to print (default: all)");
+}
+
+pub fn human_size(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", size, UNITS[unit]) }
+}
+
+/// Entry point for `vxug-scraper catalog ...`
+pub fn run_command(args: &[String]) -> error::Result<()> {
+    let mut root_dir = String::from("Downloads");
+    let mut catalog_file: Option<String> = None;
+    let mut status: Option<String> = None;
+    let mut limit: i64 = -1;
+    let mut positional: Vec<String> = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-h" | "--help" => {
+                print_command_help();
+                return Ok(());
+            }
+            "-o" | "--output-dir" | "--catalog-file" | "--status" | "--limit" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let value = args[i + 1].clone();
+                match args[i].as_str() {
+                    "--catalog-file" => catalog_file = Some(value),
+                    "--status" => status = Some(value),
+                    "--limit" => {
+                        limit = value.parse::<i64>().unwrap_or_else(|_| {
+                            eprintln!("Error: invalid limit value: {}", value);
+                            std::process::exit(1);
+                        });
+                    }
+                    _ => root_dir = value,
+                }
+                i += 2;
+            }
+            other => {
+                positional.push(other.to_string());
+                i += 1;
+            }
+        }
+    }
+
+    let path = catalog_file.unwrap_or_else(|| Path::new(&root_dir).join(CATALOG_FILE).to_string_lossy().into_owned());
+    if !Path::new(&path).exists() {
+        eprintln!("Error: no catalog at {} (run a crawl with --catalog first)", path);
+        std::process::exit(1);
+    }
+    let conn = Connection::open(&path)?;
+
+    match positional.first().map(String::as_str).unwrap_or("summary") {
+        "summary" => {
+            let dirs: i64 = conn.query_row("SELECT COUNT(*) FROM directories", [], |r| r.get(0))?;
+            println!("[*] Catalog: {}", path);
+            println!("Directories: {}", dirs);
+            let mut stmt = conn.prepare(
+                "SELECT status, COUNT(*), COALESCE(SUM(size), 0) FROM files GROUP BY status ORDER BY status",
+            )?;
+            let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?, r.get::<_, i64>(2)?)))?;
+            for row in rows {
+                let (status, count, bytes) = row?;
+                println!("Files {}: {} ({})", status, count, human_size(bytes));
+            }
+            let last: Option<String> = conn
+                .query_row("SELECT MAX(downloaded_at) FROM files", [], |r| r.get(0))
+                .optional()?
+                .flatten();
+            if let Some(last) = last {
+                println!("Last download: {}", last);
+            }
+        }
+        "files" | "find" => {
+            let pattern = if positional[0] == "find" {
+                match positional.get(1) {
+                    Some(text) => format!("%{}%", text),
+                    None => {
+                        eprintln!("Error: find requires a search text");
+                        std::process::exit(1);
+                    }
+                }
+            } else {
+                "%".to_string()
+            };
+            let mut stmt = conn.prepare(
+                "SELECT status, size, local_path, url FROM files
+                 WHERE (?1 IS NULL OR status = ?1) AND (name LIKE ?2 OR local_path LIKE ?2 OR url LIKE ?2)
+                 ORDER BY local_path LIMIT ?3",
+            )?;
+            let rows = stmt.query_map(params![status, pattern, limit], |r| {
+                Ok((r.get::<_, String>(0)?, r.get::<_, Option<i64>>(1)?, r.get::<_, String>(2)?, r.get::<_, String>(3)?))
+            })?;
+            for row in rows {
+                let (status, size, local_path, url) = row?;
+                let size = size.map(human_size).unwrap_or_else(|| "-".to_string());
+                println!("{:<10} {:>10}  {}  <- {}", status, size, local_path, url);
+            }
+        }
+        "dirs" => {
+            let mut stmt = conn.prepare(
+                "SELECT status, file_count, local_path, url, last_seen FROM directories ORDER BY local_path LIMIT ?1",
+            )?;
+            let rows = stmt.query_map(params![limit], |r| {
+                Ok((
+                    r.get::<_, String>(0)?,
+                    r.get::<_, i64>(1)?,
+                    r.get::<_, String>(2)?,
+                    r.get::<_, String>(3)?,
+                    r.get::<_, String>(4)?,
+                ))
+            })?;
+            for row in rows {
+                let (status, files, local_path, url, last_seen) = row?;
+                println!("{:<8} {:>5} files  {}  <- {} (seen {})", status, files, local_path, url, last_seen);
+            }
+        }
+        "dups" => {
+            let mut stmt = conn.prepare(
+                "SELECT sha256, local_path, kind, url FROM locations
+                 WHERE sha256 IN (SELECT sha256 FROM locations GROUP BY sha256 HAVING COUNT(*) > 1)
+                 ORDER BY sha256, kind = 'file' DESC, local_path",
+            )?;
+            let rows = stmt.query_map([], |r| {
+                Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?, r.get::<_, String>(3)?))
+            })?;
+            let mut last = String::new();
+            for row in rows {
+                let (sha256, local_path, kind, url) = row?;
+                if sha256 != last {
+                    println!("{}", sha256);
+                    last = sha256;
+                }
+                println!("  {:<9} {}  <- {}", kind, local_path, url);
+            }
+        }
+        "show" => {
+            let Some(key) = positional.get(1) else {
+                eprintln!("Error: show requires a URL or local path");
+                std::process::exit(1);
+            };
+            let row = conn
+                .query_row(
+                    "SELECT url, directory_url, local_path, size, status, first_seen, last_seen, updated_at,
+                            downloaded_at, sha256, sha1, md5
+                     FROM files WHERE url = ?1 OR local_path = ?1",
+                    params![key],
+                    |r| {
+                        let mut fields = Vec::new();
+                        for (idx, label) in [
+                            "URL", "Directory", "Local path", "Size", "Status", "First seen", "Last seen",
+                            "Updated", "Downloaded", "SHA-256", "SHA-1", "MD5",
+                        ]
+                        .iter()
+                        .enumerate()
+                        {
+                            let value: rusqlite::types::Value = r.get(idx)?;
+                            let value = match value {
+                                rusqlite::types::Value::Null => "-".to_string(),
+                                rusqlite::types::Value::Integer(n) => n.to_string(),
+                                rusqlite::types::Value::Text(s) => s,
+                                other => format!("{:?}", other),
+                            };
+                            fields.push((*label, value));
+                        }
+                        Ok(fields)
+                    },
+                )
+                .optional()?;
+            match row {
+                Some(fields) => {
+                    for (label, value) in fields {
+                        println!("{:<11} {}", format!("{}:", label), value);
+                    }
+                }
+                None => {
+                    eprintln!("[!] No catalogued file matches {}", key);
+                    std::process::exit(1);
+                }
+            }
+        }
+        other => {
+            eprintln!("Error: unknown catalog query: {}", other);
+            print_command_help();
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+/*
+    Output-root confinement.
+    Directory and file names come from remote listings and the retry list.
+    Each one is checked before it becomes part of a local path: empty names,
+    `.`/`..`, hidden names and names with separators, colons or control
+    characters are refused, and the joined path must resolve inside the output
+    directory, following any symlinked directories on the way.
+*/
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Canonical output directory, set once it exists
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Confine local paths to `root_dir` from now on
+pub fn set_root(root_dir: &str) -> io::Result<()> {
+    let _ = ROOT.set(Path::new(root_dir).canonicalize()?);
+    Ok(())
+}
+
+/// Check a single remote name before it is used as a path component
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("empty name".to_string());
+    }
+    if name == "." || name == ".." {
+        return Err("relative path component".to_string());
+    }
+    if name.starts_with('.') {
+        return Err("hidden name".to_string());
+    }
+    if name.contains(['/', '\\']) {
+        return Err("contains a path separator".to_string());
+    }
+    if name.contains(':') {
+        return Err("drive letter or alternate data stream".to_string());
+    }
+    if name.chars().any(char::is_control) {
+        return Err("contains control characters".to_string());
+    }
+    Ok(())
+}
+
+/// `path` made absolute with `.` and `..` resolved lexically
+fn normalize(path: &Path) -> io::Result<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in std::path::absolute(path)?.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other.as_os_str()),
+        }
+    }
+    Ok(out)
+}
+
+/// Check that a local path stays inside the output directory
+pub fn check(path: &str) -> Result<(), String> {
+    let Some(root) = ROOT.get() else {
+        return Ok(());
+    };
+    let normalized = normalize(Path::new(path)).map_err(|e| e.to_string())?;
+    // resolve symlinks in the part that exists; the rest cannot contain `..` any more
+    let mut existing = normalized.as_path();
+    while !existing.exists() {
+        existing = existing.parent().ok_or_else(|| "no existing ancestor".to_string())?;
+    }
+    let resolved = existing.canonicalize().map_err(|e| e.to_string())?;
+    let rest = normalized.strip_prefix(existing).unwrap_or(Path::new(""));
+    if resolved.join(rest).starts_with(root) {
+        Ok(())
+    } else {
+        Err(format!("resolves outside {}", root.display()))
+    }
+}
+
+/// Join a remote name onto a local directory, refusing names that would escape the output directory
+pub fn join(dir: &str, name: &str) -> Result<String, String> {
+    check_name(name)?;
+    let path = format!("{}/{}", dir, name);
+    check(&path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Output directory shared by the tests, since the root can only be set once
+    fn root() -> &'static Path {
+        static DIR: OnceLock<PathBuf> = OnceLock::new();
+        DIR.get_or_init(|| {
+            let base = std::env::temp_dir().join(format!("vxug-confine-{}", std::process::id()));
+            let root = base.join("root");
+            fs::create_dir_all(root.join("inside")).unwrap();
+            fs::create_dir_all(base.join("outside")).unwrap();
+            set_root(root.to_str().unwrap()).unwrap();
+            root
+        })
+    }
+
+    fn dir() -> String {
+        root().to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn refuses_relative_components() {
+        assert!(check_name("..").is_err());
+        assert!(check_name(".").is_err());
+        assert!(check_name("").is_err());
+        assert!(check_name(".hidden").is_err());
+        assert!(join(&dir(), "..").is_err());
+    }
+
+    #[test]
+    fn refuses_separators() {
+        assert!(check_name("a/b").is_err());
+        assert!(check_name("..\\evil").is_err());
+        assert!(check_name("/etc").is_err());
+        assert!(join(&dir(), "../outside").is_err());
+    }
+
+    #[test]
+    fn refuses_drive_letters_and_streams() {
+        assert!(check_name("C:").is_err());
+        assert!(check_name("C:evil.exe").is_err());
+        assert!(check_name("sample.exe:stream").is_err());
+        assert!(check_name("sample.exe::$DATA").is_err());
+    }
+
+    #[test]
+    fn refuses_control_characters() {
+        assert!(check_name("a\nb").is_err());
+        assert!(check_name("a\0b").is_err());
+    }
+
+    #[test]
+    fn accepts_plain_names() {
+        assert!(check_name("sample.exe").is_ok());
+        assert!(check_name("2023").is_ok());
+        assert!(check_name("APT 29 (report).pdf").is_ok());
+        assert_eq!(join(&dir(), "file.bin"), Ok(format!("{}/file.bin", dir())));
+        assert!(join(&format!("{}/inside", dir()), "file.bin").is_ok());
+    }
+
+    #[test]
+    fn refuses_paths_outside_the_root() {
+        assert!(check(&format!("{}/inside/../../outside/file", dir())).is_err());
+        assert!(check(&format!("{}/../outside", dir())).is_err());
+        assert!(check("/").is_err());
+        assert!(check(&format!("{}/inside/../file", dir())).is_ok());
+        assert!(check(&format!("{}/not/created/yet", dir())).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinked_parents() {
+        let link = root().join("escape");
+        let inner = root().join("alias");
+        if fs::symlink_metadata(&link).is_err() {
+            std::os::unix::fs::symlink(root().parent().unwrap().join("outside"), &link).unwrap();
+        }
+        if fs::symlink_metadata(&inner).is_err() {
+            std::os::unix::fs::symlink(root().join("inside"), &inner).unwrap();
+        }
+        assert!(join(&format!("{}/escape", dir()), "file").is_err());
+        assert!(join(&format!("{}/escape/new", dir()), "file").is_err());
+        assert!(join(&format!("{}/alias", dir()), "file").is_ok());
+    }
+}
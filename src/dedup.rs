@@ -0,0 +1,123 @@
+/*
+    Content-hash deduplication.
+    When a completed file has the same SHA-256 as a file already in the
+    mirror, the new copy is replaced by a hardlink, reflink or symlink to the
+    existing one. Replacements go through a temporary file and a rename, so
+    a failure leaves the downloaded copy in place.
+*/
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+/// How duplicates are replaced, set with --dedup
+pub static MODE: OnceLock<Mode> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Hardlink,
+    Reflink,
+    Symlink,
+}
+
+impl Mode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "hardlink" => Some(Mode::Hardlink),
+            "reflink" => Some(Mode::Reflink),
+            "symlink" => Some(Mode::Symlink),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Hardlink => "hardlink",
+            Mode::Reflink => "reflink",
+            Mode::Symlink => "symlink",
+        }
+    }
+}
+
+/// Path to `target` relative to the directory `from_dir`, for symlinks that survive moving the mirror
+pub fn relative_path(from_dir: &Path, target: &Path) -> PathBuf {
+    let from: Vec<Component> = from_dir.components().filter(|c| *c != Component::CurDir).collect();
+    let to: Vec<Component> = target.components().filter(|c| *c != Component::CurDir).collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut rel = PathBuf::new();
+    for _ in common..from.len() {
+        rel.push("..");
+    }
+    for c in &to[common..] {
+        rel.push(c.as_os_str());
+    }
+    rel
+}
+
+#[cfg(unix)]
+pub fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let src_file = fs::File::open(src)?;
+    let dst_file = fs::File::create(dst)?;
+    let ret = unsafe { libc::ioctl(dst_file.as_raw_fd(), libc::FICLONE, src_file.as_raw_fd()) };
+    if ret != 0 {
+        let err = io::Error::last_os_error();
+        drop(dst_file);
+        let _ = fs::remove_file(dst);
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "reflinks are only supported on Linux"))
+}
+
+/// Create a link of the given kind at `link` pointing to `original`
+pub fn link(mode: Mode, original: &Path, link: &Path) -> io::Result<()> {
+    match mode {
+        Mode::Hardlink => fs::hard_link(original, link),
+        Mode::Reflink => reflink(original, link),
+        Mode::Symlink => {
+            let dir = link.parent().unwrap_or(Path::new(""));
+            symlink(&relative_path(dir, original), link)
+        }
+    }
+}
+
+/// Replace `duplicate` with a link to `original`. Returns the bytes freed.
+pub fn replace(mode: Mode, original: &Path, duplicate: &Path) -> io::Result<u64> {
+    // an earlier run may have made the original a symlink itself; link to the real copy
+    let mut original = original.to_path_buf();
+    for _ in 0..8 {
+        if !fs::symlink_metadata(&original)?.file_type().is_symlink() {
+            break;
+        }
+        let target = fs::read_link(&original)?;
+        original = original.parent().unwrap_or(Path::new("")).join(target);
+    }
+    let original = original.as_path();
+    let size = fs::metadata(duplicate)?.len();
+    let mut tmp = duplicate.as_os_str().to_owned();
+    tmp.push(".dedup");
+    let tmp = PathBuf::from(tmp);
+    let _ = fs::remove_file(&tmp);
+    link(mode, original, &tmp)?;
+    if let Err(e) = fs::rename(&tmp, duplicate) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(size)
+}
@@ -0,0 +1,101 @@
+/*
+    Crate error type.
+    Failures in the crawl and download paths are classified so callers can
+    decide what to do with them: rotate the route, defer the item for a
+    retry or record it as failed. Nothing in these paths panics.
+*/
+
+use reqwest::StatusCode;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Connection, TLS, proxy, timeout or body transfer failure
+    Network(reqwest::Error),
+    /// The server answered with a non-success status
+    HttpStatus { url: String, status: StatusCode },
+    /// Local file system failure on the given path
+    Io { path: PathBuf, source: io::Error },
+    /// A URL or response could not be understood
+    Parse(String),
+    /// The body is not the expected file, e.g. an HTML interstitial
+    Validation(String),
+    /// Throughput stayed below the minimum for a whole stall window
+    Stalled { bytes: u64, secs: u64 },
+    /// Free space on the output volume is below the file's size plus the reserve
+    DiskFull { dir: String, free: u64, needed: u64, reserve: u64 },
+    /// aria2c failed to download the file
+    Aria2(String),
+    /// The SQLite catalog could not be read or written
+    Catalog(rusqlite::Error),
+    /// Shutdown was requested while the transfer was running
+    Interrupted,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        Error::Io { path: path.as_ref().to_path_buf(), source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Network(e) => write!(f, "{}", e),
+            Error::HttpStatus { url, status } => write!(f, "HTTP status {} from {}", status, url),
+            Error::Io { path, source } => write!(f, "I/O error on {}: {}", path.display(), source),
+            Error::Parse(reason) => write!(f, "parse error: {}", reason),
+            Error::Validation(reason) => write!(f, "rejected: {}", reason),
+            Error::Stalled { bytes, secs } => write!(f, "transfer stalled ({} bytes in the last {}s)", bytes, secs),
+            Error::DiskFull { dir, free, needed, reserve } => write!(
+                f,
+                "not enough disk space for {}: {} bytes free, {} needed plus a {}-byte reserve",
+                dir, free, needed, reserve
+            ),
+            Error::Aria2(reason) => write!(f, "aria2c: {}", reason),
+            Error::Catalog(e) => write!(f, "catalog: {}", e),
+            Error::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network(e) => Some(e),
+            Error::Io { source, .. } => Some(source),
+            Error::Catalog(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        match (e.status(), e.url()) {
+            (Some(status), Some(url)) => Error::HttpStatus { url: url.to_string(), status },
+            _ => Error::Network(e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Catalog(e)
+    }
+}
+
+/// Attach the path to an I/O error: `fs::write(&path, data).at(&path)?`
+pub trait IoContext<T> {
+    fn at(self, path: impl AsRef<Path>) -> Result<T>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+    fn at(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|e| Error::io(path, e))
+    }
+}
@@ -0,0 +1,185 @@
+/*
+    Size estimation (--estimate).
+    The crawl lists the tree as usual but downloads nothing. Each file's size
+    is taken from the listing page when it shows one, from the local copy
+    when it is complete, or else from a HEAD request. The report gives the
+    total, present and remaining bytes per collection and subdirectory.
+    --budget measures the same way before choosing what to download.
+*/
+
+use crate::catalog::human_size;
+use crate::metadata;
+use crate::pool::{self, ClientPool};
+use reqwest::header::CONTENT_LENGTH;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path};
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::time::SystemTime;
+
+/// Measure listed files instead of downloading them
+pub static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static FILES: Mutex<Vec<RemoteFile>> = Mutex::new(Vec::new());
+
+/// A listed file with its remote size and what is already on disk
+#[derive(Clone, Debug)]
+pub struct RemoteFile {
+    pub name: String,
+    pub url: String,
+    pub file_path: String,
+    pub dir: String,
+    /// Remote size, None when neither the listing nor the server gave one
+    pub size: Option<u64>,
+    /// Bytes already on disk, in the file itself or its `.part`
+    pub present: u64,
+    pub complete: bool,
+    /// Listing date, Last-Modified or the mtime of a complete local copy
+    pub modified: Option<SystemTime>,
+}
+
+impl RemoteFile {
+    /// Bytes still to download, None when the size is unknown
+    pub fn remaining(&self) -> Option<u64> {
+        if self.complete {
+            return Some(0);
+        }
+        self.size.map(|size| size.saturating_sub(self.present))
+    }
+}
+
+#[derive(Default)]
+struct Totals {
+    files: usize,
+    unknown: usize,
+    size: u64,
+    present: u64,
+    remaining: u64,
+}
+
+/// Size and Last-Modified from a HEAD request
+async fn head(pool: &ClientPool, url: &str) -> (Option<u64>, Option<SystemTime>) {
+    let route = pool.current();
+    match route.client.head(url).send().await.and_then(|r| r.error_for_status()) {
+        Ok(resp) => {
+            pool.success(&route);
+            let headers = resp.headers();
+            let size = headers.get(CONTENT_LENGTH).and_then(|h| h.to_str().ok()).and_then(|s| s.parse().ok());
+            (size, metadata::last_modified(headers))
+        }
+        Err(e) => {
+            eprintln!("[!] Failed to HEAD {}: {}", url, e);
+            pool::report_error(pool, &route, &e);
+            (None, None)
+        }
+    }
+}
+
+/// Work out the remote size and local state of one listed file
+pub async fn measure(pool: &ClientPool, name: &str, url: &str, file_path: &str, dir: &str, listed: Option<u64>) -> RemoteFile {
+    let local = fs::metadata(file_path).ok().filter(|m| m.is_file());
+    let complete = local.is_some();
+    let present = match &local {
+        Some(m) => m.len(),
+        None => fs::metadata(format!("{}.part", file_path)).map(|m| m.len()).unwrap_or(0),
+    };
+    // completed files carry the server's timestamp as their mtime
+    let mut modified = metadata::listing_date(url).or_else(|| local.as_ref().and_then(|m| m.modified().ok()));
+    let size = match (listed, complete) {
+        (Some(size), _) => Some(size),
+        // a complete local copy is as good as a HEAD request
+        (None, true) => Some(present),
+        (None, false) => {
+            let (size, last_modified) = head(pool, url).await;
+            modified = modified.or(last_modified);
+            size
+        }
+    };
+    RemoteFile {
+        name: name.to_string(),
+        url: url.to_string(),
+        file_path: file_path.to_string(),
+        dir: dir.to_string(),
+        size,
+        present,
+        complete,
+        modified,
+    }
+}
+
+/// Keep a measured file for the report
+pub fn record(file: RemoteFile) {
+    FILES.lock().unwrap().push(file);
+}
+
+/// Every file measured so far
+pub fn files() -> Vec<RemoteFile> {
+    FILES.lock().unwrap().clone()
+}
+
+/// Components of `dir` below the output directory
+pub fn relative_dir(root_dir: &str, dir: &str) -> Vec<String> {
+    Path::new(dir)
+        .strip_prefix(root_dir)
+        .unwrap_or(Path::new(dir))
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Print the totals of every directory below `root_dir`, indented as a tree
+pub fn print_report(root_dir: &str) {
+    let files = files();
+    let mut tree: BTreeMap<Vec<String>, Totals> = BTreeMap::new();
+    for file in &files {
+        let parts = relative_dir(root_dir, &file.dir);
+        // every ancestor, the output directory itself included, gets the file's numbers
+        for depth in 0..=parts.len() {
+            let totals = tree.entry(parts[..depth].to_vec()).or_default();
+            totals.files += 1;
+            totals.present += file.present;
+            match (file.size, file.remaining()) {
+                (Some(size), Some(remaining)) => {
+                    totals.size += size;
+                    totals.remaining += remaining;
+                }
+                _ => totals.unknown += 1,
+            }
+        }
+    }
+
+    println!("=== Size Estimate ===");
+    println!("{:>8} {:>10} {:>10} {:>10}  Directory", "Files", "Size", "Present", "Remaining");
+    let size = |bytes: u64| human_size(bytes as i64);
+    for (parts, totals) in &tree {
+        let label = match parts.last() {
+            Some(name) => format!("{}{}", "  ".repeat(parts.len() - 1), name),
+            None => continue,
+        };
+        let unknown = if totals.unknown > 0 { format!(" ({} of unknown size)", totals.unknown) } else { String::new() };
+        println!(
+            "{:>8} {:>10} {:>10} {:>10}  {}{}",
+            totals.files,
+            size(totals.size),
+            size(totals.present),
+            size(totals.remaining),
+            label,
+            unknown
+        );
+    }
+    let total = tree.remove(&Vec::new()).unwrap_or_default();
+    println!("Files listed: {}", total.files);
+    println!("Total size: {}", size(total.size));
+    println!("Already present: {}", size(total.present));
+    println!("Still to download: {}", size(total.remaining));
+    if total.unknown > 0 {
+        println!("Files of unknown size: {} (not counted above)", total.unknown);
+    }
+    if let Ok(free) = crate::space::free_space(Path::new(root_dir)) {
+        println!("Free space in {}: {}", root_dir, size(free));
+    }
+}
@@ -0,0 +1,378 @@
+/*
+    Safe extraction into a quarantine directory (--extract).
+    Downloaded archives are unpacked with the archive password list into a
+    separate root, mirroring the collection tree. Entry names that would
+    escape the destination are skipped, decompression is capped in total
+    size, file count and ratio, extracted files never get execute
+    permissions and executables can be renamed to `<name>.malware`.
+    The original archives are only read.
+*/
+
+use crate::archive::{self, Format};
+use crate::manifest;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+/// Suffix appended to executables with --rename-executables
+pub const MALWARE_SUFFIX: &str = ".malware";
+
+/// Largest decompressed total per archive (--extract-max-size)
+pub static MAX_TOTAL_BYTES: AtomicU64 = AtomicU64::new(4 << 30);
+/// Most files per archive (--extract-max-files)
+pub static MAX_FILES: AtomicUsize = AtomicUsize::new(10_000);
+/// Largest ratio of decompressed to archive size (--extract-max-ratio)
+pub static MAX_RATIO: AtomicU64 = AtomicU64::new(200);
+/// Append MALWARE_SUFFIX to executables (--rename-executables)
+pub static RENAME_EXECUTABLES: AtomicBool = AtomicBool::new(false);
+
+/// Small archives may always expand this far, whatever their ratio
+const RATIO_FLOOR_BYTES: u64 = 1 << 20;
+
+/// Quarantine root and the output directory it mirrors, set with --extract
+static ROOTS: OnceLock<(PathBuf, PathBuf)> = OnceLock::new();
+
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "exe", "dll", "scr", "sys", "com", "cpl", "ocx", "msi", "bat", "cmd", "ps1", "vbs", "vbe", "js", "jse", "wsf",
+    "hta", "jar", "lnk", "sh", "elf", "so", "dylib", "apk",
+];
+
+/// What an extraction produced
+pub struct Summary {
+    pub dest: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+    pub renamed: usize,
+    /// Entries left out, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Turn extraction on, writing under `quarantine` the tree found under `output_root`
+pub fn enable(quarantine: PathBuf, output_root: PathBuf) {
+    let _ = ROOTS.set((quarantine, output_root));
+}
+
+/// Relative path of an archive entry, or None when it is absolute or climbs out with `..`
+pub fn safe_relative(name: &str) -> Option<PathBuf> {
+    if name.starts_with(['/', '\\']) || name.contains('\0') {
+        return None;
+    }
+    let mut path = PathBuf::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => return None,
+            // drive letters and NTFS alternate data streams
+            _ if part.contains(':') => return None,
+            _ => path.push(part),
+        }
+    }
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+/// Whether a file is a program, from its extension or its leading bytes
+pub fn is_executable(name: &str, head: &[u8]) -> bool {
+    let by_name = name
+        .rsplit_once('.')
+        .is_some_and(|(_, ext)| EXECUTABLE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+    by_name
+        || head.starts_with(b"MZ")
+        || head.starts_with(b"\x7fELF")
+        || head.starts_with(&[0xCF, 0xFA, 0xED, 0xFE])
+        || head.starts_with(&[0xCE, 0xFA, 0xED, 0xFE])
+}
+
+fn too_big(reason: String) -> io::Error {
+    io::Error::other(format!("decompression limit reached: {}", reason))
+}
+
+/// Create a file that never carries execute permissions
+fn create_plain(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o644);
+    options.open(path)
+}
+
+/// Unpack one archive into `dest`. Entries are written to a staging directory
+/// that replaces `dest` only once the whole archive is out.
+pub fn extract(archive_path: &Path, format: Format, dest: &Path) -> Result<Summary, String> {
+    let archive_size = fs::metadata(archive_path).map_err(|e| e.to_string())?.len();
+    let ratio_limit = archive_size.saturating_mul(MAX_RATIO.load(Ordering::Relaxed)).max(RATIO_FLOOR_BYTES);
+    let limit = MAX_TOTAL_BYTES.load(Ordering::Relaxed).min(ratio_limit);
+    let max_files = MAX_FILES.load(Ordering::Relaxed);
+    let rename = RENAME_EXECUTABLES.load(Ordering::Relaxed);
+
+    let mut staging = dest.as_os_str().to_owned();
+    staging.push(".partial");
+    let staging = PathBuf::from(staging);
+    if staging.exists() {
+        fs::remove_dir_all(&staging).map_err(|e| e.to_string())?;
+    }
+    fs::create_dir_all(&staging).map_err(|e| e.to_string())?;
+
+    let mut summary = Summary { dest: dest.to_path_buf(), files: 0, bytes: 0, renamed: 0, skipped: Vec::new() };
+    let result = archive::for_each_entry(archive_path, format, &archive::passwords(), limit, |entry, data| {
+        let Some(rel) = safe_relative(&entry.name) else {
+            summary.skipped.push((entry.name.clone(), "path leaves the extraction directory".to_string()));
+            return Ok(true);
+        };
+        let path = staging.join(&rel);
+        if entry.is_dir {
+            fs::create_dir_all(&path)?;
+            return Ok(true);
+        }
+        summary.files += 1;
+        if summary.files > max_files {
+            return Err(too_big(format!("more than {} files", max_files)));
+        }
+        let remaining = limit - summary.bytes;
+        if entry.size > remaining {
+            return Err(too_big(format!("more than {} bytes", limit)));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = match create_plain(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                summary.skipped.push((entry.name.clone(), "duplicate entry".to_string()));
+                return Ok(true);
+            }
+            Err(e) => return Err(e),
+        };
+        // the declared size is not trusted: stop one byte past the budget
+        let written = io::copy(&mut data.take(remaining + 1), &mut file)?;
+        if written > remaining {
+            drop(file);
+            return Err(too_big(format!("more than {} bytes", limit)));
+        }
+        summary.bytes += written;
+        drop(file);
+
+        if rename {
+            let mut head = [0u8; 4];
+            let n = File::open(&path)?.read(&mut head)?;
+            if is_executable(&entry.name, &head[..n]) {
+                let mut renamed = path.as_os_str().to_owned();
+                renamed.push(MALWARE_SUFFIX);
+                fs::rename(&path, renamed)?;
+                summary.renamed += 1;
+            }
+        }
+        Ok(true)
+    });
+
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    if dest.exists() {
+        fs::remove_dir_all(dest).map_err(|e| e.to_string())?;
+    }
+    fs::rename(&staging, dest).map_err(|e| e.to_string())?;
+    Ok(summary)
+}
+
+/// Extract a downloaded archive into the quarantine root when --extract is set.
+/// The destination is a directory named after the archive, at the same place
+/// in the tree as the archive under the output directory.
+pub fn extract_download(name: &str, file_path: &Path) -> Option<Result<Summary, String>> {
+    let (quarantine, output_root) = ROOTS.get()?;
+    let format = Format::from_name(name)?;
+    let Some(rel) = safe_relative(&manifest::relative_key(output_root, file_path)) else {
+        return Some(Err(format!("{} is not under the output directory", file_path.display())));
+    };
+    Some(extract(file_path, format, &quarantine.join(rel)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Mutex, MutexGuard};
+    use zip::write::SimpleFileOptions;
+
+    /// Limits are process-wide, so the tests that extract take turns
+    static LIMITS: Mutex<()> = Mutex::new(());
+
+    fn limits(total: u64, files: usize, ratio: u64, rename: bool) -> MutexGuard<'static, ()> {
+        let guard = LIMITS.lock().unwrap_or_else(|e| e.into_inner());
+        MAX_TOTAL_BYTES.store(total, Ordering::Relaxed);
+        MAX_FILES.store(files, Ordering::Relaxed);
+        MAX_RATIO.store(ratio, Ordering::Relaxed);
+        RENAME_EXECUTABLES.store(rename, Ordering::Relaxed);
+        guard
+    }
+
+    /// Fresh directory for one test
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vxug-extract-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write a zip holding `entries` to `dir/sample.zip`
+    fn zip(dir: &Path, method: zip::CompressionMethod, entries: &[(&str, &[u8])]) -> PathBuf {
+        let path = dir.join("sample.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+        for (name, data) in entries {
+            writer.start_file(*name, SimpleFileOptions::default().compression_method(method)).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    fn partial(dest: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.partial", dest.display()))
+    }
+
+    #[test]
+    fn extracts_into_dest_and_removes_staging() {
+        let _limits = limits(1 << 20, 10, 200, false);
+        let dir = scratch("ok");
+        let archive = zip(&dir, zip::CompressionMethod::Stored, &[("a/one.txt", b"one"), ("two.txt", b"two")]);
+        let dest = dir.join("out");
+        let summary = extract(&archive, Format::Zip, &dest).unwrap();
+        assert_eq!((summary.files, summary.bytes), (2, 6));
+        assert_eq!(fs::read(dest.join("a").join("one.txt")).unwrap(), b"one");
+        assert!(!partial(&dest).exists());
+    }
+
+    #[test]
+    fn total_size_limit_aborts_without_leftovers() {
+        let _limits = limits(1000, 10, 200, false);
+        let dir = scratch("size");
+        let chunk = [b'x'; 800];
+        let archive = zip(&dir, zip::CompressionMethod::Stored, &[("a.bin", &chunk), ("b.bin", &chunk)]);
+        let dest = dir.join("out");
+        let err = extract(&archive, Format::Zip, &dest).err().unwrap();
+        assert!(err.contains("more than 1000 bytes"), "{}", err);
+        assert!(!dest.exists());
+        assert!(!partial(&dest).exists());
+    }
+
+    #[test]
+    fn file_count_limit_aborts_without_leftovers() {
+        let _limits = limits(1 << 20, 3, 200, false);
+        let dir = scratch("count");
+        let entries: Vec<(String, Vec<u8>)> = (0..5).map(|i| (format!("{}.txt", i), vec![b'x'; 10])).collect();
+        let entries: Vec<(&str, &[u8])> = entries.iter().map(|(n, d)| (n.as_str(), d.as_slice())).collect();
+        let archive = zip(&dir, zip::CompressionMethod::Stored, &entries);
+        let dest = dir.join("out");
+        let err = extract(&archive, Format::Zip, &dest).err().unwrap();
+        assert!(err.contains("more than 3 files"), "{}", err);
+        assert!(!dest.exists());
+        assert!(!partial(&dest).exists());
+    }
+
+    #[test]
+    fn ratio_limit_aborts_without_leftovers() {
+        let _limits = limits(1 << 30, 10, 2, false);
+        let dir = scratch("ratio");
+        // 4 MB of zeros deflates to a few KB, past the 1 MB floor and 2x the archive size
+        let zeros = vec![0u8; 4 << 20];
+        let archive = zip(&dir, zip::CompressionMethod::Deflated, &[("bomb.bin", &zeros)]);
+        assert!(fs::metadata(&archive).unwrap().len() * 2 < RATIO_FLOOR_BYTES);
+        let dest = dir.join("out");
+        let err = extract(&archive, Format::Zip, &dest).err().unwrap();
+        assert!(err.contains(&format!("more than {} bytes", RATIO_FLOOR_BYTES)), "{}", err);
+        assert!(!dest.exists());
+        assert!(!partial(&dest).exists());
+    }
+
+    #[test]
+    fn failure_keeps_previous_extraction_and_clears_stale_staging() {
+        let _limits = limits(1 << 20, 1, 200, false);
+        let dir = scratch("staging");
+        let dest = dir.join("out");
+        fs::create_dir_all(&dest).unwrap();
+        fs::write(dest.join("earlier.txt"), b"earlier").unwrap();
+        // left behind by a run that crashed mid-extraction
+        fs::create_dir_all(partial(&dest)).unwrap();
+        fs::write(partial(&dest).join("stale.txt"), b"stale").unwrap();
+        let archive = zip(&dir, zip::CompressionMethod::Stored, &[("a.txt", b"a"), ("b.txt", b"b")]);
+        assert!(extract(&archive, Format::Zip, &dest).is_err());
+        assert!(!partial(&dest).exists());
+        assert_eq!(fs::read(dest.join("earlier.txt")).unwrap(), b"earlier");
+        assert!(!dest.join("a.txt").exists());
+    }
+
+    #[test]
+    fn rename_executables_by_name_and_header() {
+        let _limits = limits(1 << 20, 10, 200, true);
+        let dir = scratch("rename");
+        let archive = zip(
+            &dir,
+            zip::CompressionMethod::Stored,
+            &[
+                ("dropper.exe", b"not really"),
+                ("invoice.pdf", b"MZ\x90\x00"),
+                ("payload.dat", b"\x7fELF\x02"),
+                ("notes.txt", b"plain text"),
+            ],
+        );
+        let dest = dir.join("out");
+        let summary = extract(&archive, Format::Zip, &dest).unwrap();
+        assert_eq!(summary.renamed, 3);
+        for name in ["dropper.exe", "invoice.pdf", "payload.dat"] {
+            assert!(dest.join(format!("{}{}", name, MALWARE_SUFFIX)).exists(), "{}", name);
+            assert!(!dest.join(name).exists(), "{}", name);
+        }
+        assert!(dest.join("notes.txt").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dest.join("notes.txt")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o111, 0);
+        }
+    }
+
+    #[test]
+    fn safe_relative_refuses_parent_components() {
+        assert_eq!(safe_relative(".."), None);
+        assert_eq!(safe_relative("../evil"), None);
+        assert_eq!(safe_relative("a/../../evil"), None);
+        assert_eq!(safe_relative("a\\..\\..\\evil"), None);
+        // even a `..` that would stay inside is refused
+        assert_eq!(safe_relative("a/../b"), None);
+    }
+
+    #[test]
+    fn safe_relative_refuses_absolute_paths() {
+        assert_eq!(safe_relative("/etc/passwd"), None);
+        assert_eq!(safe_relative("\\Windows\\System32"), None);
+        assert_eq!(safe_relative("\\\\server\\share\\file"), None);
+    }
+
+    #[test]
+    fn safe_relative_refuses_drive_letters_and_streams() {
+        assert_eq!(safe_relative("C:\\Windows\\evil.exe"), None);
+        assert_eq!(safe_relative("C:evil.exe"), None);
+        assert_eq!(safe_relative("dir/sample.exe:stream"), None);
+        assert_eq!(safe_relative("sample.exe::$DATA"), None);
+    }
+
+    #[test]
+    fn safe_relative_refuses_nul() {
+        assert_eq!(safe_relative("a\0b"), None);
+    }
+
+    #[test]
+    fn safe_relative_splits_on_both_separators() {
+        assert_eq!(safe_relative("a/b\\c.bin"), Some(PathBuf::from("a").join("b").join("c.bin")));
+        assert_eq!(safe_relative("./a//b/"), Some(PathBuf::from("a").join("b")));
+    }
+
+    #[test]
+    fn safe_relative_refuses_empty_paths() {
+        assert_eq!(safe_relative(""), None);
+        assert_eq!(safe_relative("./"), None);
+        assert_eq!(safe_relative("."), None);
+    }
+}
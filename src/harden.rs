@@ -0,0 +1,329 @@
+/*
+    Sample-safe output (--sample-safe) and the `harden` command.
+    Completed files are made read-only and non-executable, every directory
+    holding samples gets a warning marker file. `vxug-scraper harden` applies
+    the same to an existing tree, and with --defang renames files with
+    executable extensions to `<name>.malware`.
+*/
+
+use crate::catalog::{CATALOG_FILE, Catalog};
+use crate::error::{self, IoContext};
+use crate::extract;
+use crate::manifest::{self, Manifest};
+use crate::store;
+use crate::verify;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Warning file written to every directory holding samples
+pub const MARKER_FILE: &str = "_LIVE_MALWARE_WARNING.txt";
+
+const MARKER_TEXT: &str = "\
+WARNING: this directory contains LIVE MALWARE.
+
+The files here were downloaded or extracted for analysis and can infect the
+machine that runs them. Do not open, execute or double-click them outside an
+isolated analysis environment. Files have been made read-only and
+non-executable on purpose; do not change that.
+";
+
+/// Harden completed files as they are written
+pub static SAMPLE_SAFE: AtomicBool = AtomicBool::new(false);
+
+/// Directories that already have a marker this run
+static MARKED: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+/// Name a file gets from `harden --defang`. Only the name is looked at, so
+/// running it again renames nothing new.
+fn defang_name(name: &str) -> String {
+    if !name.ends_with(extract::MALWARE_SUFFIX) && extract::is_executable(name, &[]) {
+        format!("{}{}", name, extract::MALWARE_SUFFIX)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Make a file read-only and non-executable. Symlinks are followed.
+pub fn lock_file(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o444))
+    }
+    #[cfg(not(unix))]
+    {
+        let mut permissions = fs::metadata(path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(path, permissions)
+    }
+}
+
+/// Write the warning marker into `dir` unless it is there already
+pub fn mark_dir(dir: &Path) -> io::Result<()> {
+    if !MARKED.lock().unwrap().get_or_insert_with(HashSet::new).insert(dir.to_path_buf()) {
+        return Ok(());
+    }
+    let marker = dir.join(MARKER_FILE);
+    if marker.exists() {
+        return Ok(());
+    }
+    fs::write(&marker, MARKER_TEXT)?;
+    lock_file(&marker)
+}
+
+/// Harden a completed download when --sample-safe is set
+pub fn apply(file_path: &str) {
+    if !SAMPLE_SAFE.load(Ordering::Relaxed) {
+        return;
+    }
+    let path = Path::new(file_path);
+    let mut dirs = vec![path.parent().unwrap_or(Path::new(".")).to_path_buf()];
+    // in the content-addressed layout the data lives in the store
+    if let Ok(target) = fs::canonicalize(path)
+        && let Some(parent) = target.parent()
+        && fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+    {
+        dirs.push(parent.to_path_buf());
+    }
+    let result = lock_file(path).and_then(|_| dirs.iter().try_for_each(|dir| mark_dir(dir)));
+    if let Err(e) = result {
+        eprintln!("[!] Failed to harden {}: {}", file_path, e);
+    }
+}
+
+/// What hardening a tree did
+#[derive(Default)]
+pub struct Report {
+    pub files: usize,
+    pub dirs: usize,
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    /// Symlinks to files and their resolved targets
+    pub links: Vec<(PathBuf, PathBuf)>,
+    pub errors: usize,
+}
+
+fn skipped(name: &str) -> bool {
+    // partial downloads must stay writable to be resumed, sidecars are rewritten on re-download
+    verify::is_internal(name) || name.ends_with(".part") || name.ends_with(".aria2")
+}
+
+/// Harden every regular file below `dir`, marking each directory that holds one.
+/// Symlinks to files, such as the view of the content-addressed layout, are
+/// renamed by --defang but not locked; their targets are locked where they live.
+pub fn harden_tree(dir: &Path, defang: bool, report: &mut Report) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        eprintln!("[!] Cannot read directory {}", dir.display());
+        report.errors += 1;
+        return;
+    };
+    let mut has_files = false;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if file_type.is_dir() {
+            // store objects are named by hash, only the view gets defanged names
+            harden_tree(&path, defang && name != store::STORE_DIR, report);
+            continue;
+        }
+        // a link may dangle because its target was renamed earlier in the walk
+        let link = file_type.is_symlink() && !fs::metadata(&path).is_ok_and(|m| m.is_dir());
+        if !(file_type.is_file() || link) || skipped(&name) {
+            continue;
+        }
+        has_files = true;
+        let target = if link { fs::read_link(&path).ok().and_then(|t| resolve(&path.with_file_name(t))) } else { None };
+        let mut path = path;
+        if defang {
+            let new_name = defang_name(&name);
+            if new_name != name {
+                let new_path = path.with_file_name(&new_name);
+                if let Err(e) = rename_with_sidecar(&path, &new_path) {
+                    eprintln!("[!] Failed to rename {}: {}", path.display(), e);
+                    report.errors += 1;
+                } else {
+                    report.renamed.push((path.clone(), new_path.clone()));
+                    path = new_path;
+                }
+            }
+        }
+        if let Some(target) = target {
+            report.links.push((path, target));
+            continue;
+        }
+        match lock_file(&path) {
+            Ok(()) => report.files += 1,
+            Err(e) => {
+                eprintln!("[!] Failed to harden {}: {}", path.display(), e);
+                report.errors += 1;
+            }
+        }
+    }
+    if has_files {
+        match mark_dir(dir) {
+            Ok(()) => report.dirs += 1,
+            Err(e) => {
+                eprintln!("[!] Failed to write {} in {}: {}", MARKER_FILE, dir.display(), e);
+                report.errors += 1;
+            }
+        }
+    }
+}
+
+fn rename_with_sidecar(old: &Path, new: &Path) -> io::Result<()> {
+    if new.exists() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", new.display())));
+    }
+    fs::rename(old, new)?;
+    let sidecar = |p: &Path| {
+        let mut s = p.as_os_str().to_owned();
+        s.push(crate::metadata::SIDECAR_SUFFIX);
+        PathBuf::from(s)
+    };
+    if sidecar(old).exists() {
+        fs::rename(sidecar(old), sidecar(new))?;
+    }
+    Ok(())
+}
+
+/// Absolute path of `path` with its directory resolved, whether or not the file exists
+fn resolve(path: &Path) -> Option<PathBuf> {
+    Some(fs::canonicalize(path.parent()?).ok()?.join(path.file_name()?))
+}
+
+/// Point symlinks whose target was renamed, such as --dedup symlink copies,
+/// at the target's new name
+fn relink(report: &Report) {
+    let moved: HashMap<PathBuf, PathBuf> = report
+        .renamed
+        .iter()
+        .filter_map(|(old, new)| Some((resolve(old)?, resolve(new)?)))
+        .collect();
+    for (link, target) in &report.links {
+        let (Some(new_target), Some(link)) = (moved.get(target), resolve(link)) else {
+            continue;
+        };
+        if let Err(e) = store::link_view(new_target, &link) {
+            eprintln!("[!] Failed to re-point {}: {}", link.display(), e);
+        }
+    }
+}
+
+/// Point the SHA256SUMS line of a renamed file at its new name
+fn rename_in_sums(old: &Path, new: &Path) -> io::Result<()> {
+    let sums = old.with_file_name(manifest::SUMS_FILE);
+    let (Some(old_name), Some(new_name)) = (old.file_name(), new.file_name()) else {
+        return Ok(());
+    };
+    let Ok(content) = fs::read_to_string(&sums) else {
+        return Ok(());
+    };
+    let (old_name, new_name) = (old_name.to_string_lossy(), new_name.to_string_lossy());
+    let out: String = content
+        .lines()
+        .map(|line| match line.split_once("  ") {
+            Some((hash, name)) if name == old_name => format!("{}  {}\n", hash, new_name),
+            _ => format!("{}\n", line),
+        })
+        .collect();
+    fs::write(sums, out)
+}
+
+fn print_command_help() {
+    println!("Usage:");
+    println!("  vxug-scraper harden [OPTIONS]");
+    println!();
+    println!("Make an existing mirror sample-safe: read-only, non-executable files and a");
+    println!("{} in every directory.", MARKER_FILE);
+    println!();
+    println!("Options:");
+    println!("  -o, --output-dir <DIR>         Tree to harden (default: Downloads)");
+    println!("  --catalog-file <FILE>          Catalog to update after --defang (default: <DIR>/{})", CATALOG_FILE);
+    println!("  --defang                       Rename files with executable extensions to <name>{}", extract::MALWARE_SUFFIX);
+}
+
+/// Entry point for `vxug-scraper harden ...`
+pub fn run_command(args: &[String]) -> error::Result<()> {
+    let mut root_dir = String::from("Downloads");
+    let mut catalog_file: Option<String> = None;
+    let mut defang = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-h" | "--help" => {
+                print_command_help();
+                return Ok(());
+            }
+            "-o" | "--output-dir" | "--catalog-file" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                if args[i] == "--catalog-file" {
+                    catalog_file = Some(args[i + 1].clone());
+                } else {
+                    root_dir = args[i + 1].clone();
+                }
+                i += 2;
+            }
+            "--defang" => {
+                defang = true;
+                i += 1;
+            }
+            other => {
+                eprintln!("Error: unknown harden option: {}", other);
+                print_command_help();
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let root = PathBuf::from(&root_dir);
+    if !root.is_dir() {
+        eprintln!("Error: {} is not a directory", root.display());
+        std::process::exit(1);
+    }
+    let mut report = Report::default();
+    harden_tree(&root, defang, &mut report);
+
+    if !report.renamed.is_empty() {
+        relink(&report);
+        // a mirror written with --manifest-format sha256sums has no manifest.json
+        let manifest_path = root.join(manifest::MANIFEST_FILE);
+        let manifest = if manifest_path.exists() {
+            Some(Manifest::open(&root_dir, manifest::Format::Json).at(&manifest_path)?)
+        } else {
+            None
+        };
+        for (old, new) in &report.renamed {
+            println!("[*] Renamed {} -> {}", old.display(), new.display());
+            if let Some(manifest) = &manifest {
+                manifest.rename(old, new);
+            }
+            rename_in_sums(old, new).at(old.with_file_name(manifest::SUMS_FILE))?;
+        }
+        if let Some(manifest) = &manifest {
+            manifest.save().at(&manifest_path)?;
+        }
+        let catalog_path = catalog_file.map(PathBuf::from).unwrap_or_else(|| root.join(CATALOG_FILE));
+        if catalog_path.exists() {
+            let rows = Catalog::open(&catalog_path)?.rename_paths(&root, &report.renamed)?;
+            println!("[*] Updated {} paths in {}", rows, catalog_path.display());
+        }
+    }
+    println!(
+        "[+] Hardened {} files in {} directories, renamed {}, {} errors",
+        report.files,
+        report.dirs,
+        report.renamed.len(),
+        report.errors
+    );
+    Ok(())
+}
@@ -0,0 +1,70 @@
+/*
+    SHA-256 / SHA-1 / MD5 digests of downloaded files.
+    The built-in downloader feeds every chunk through a `Hasher` as it is
+    written, so files are hashed without a second pass over the data.
+*/
+
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Hex-encoded digests of one file
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hashes {
+    pub sha256: String,
+    pub sha1: String,
+    pub md5: String,
+}
+
+/// Incremental hasher computing all three digests at once
+#[derive(Clone, Default)]
+pub struct Hasher {
+    sha256: Sha256,
+    sha1: Sha1,
+    md5: Md5,
+}
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+        self.sha1.update(data);
+        self.md5.update(data);
+    }
+
+    /// Feed everything `reader` yields into the hasher, returning the number of bytes read
+    pub fn update_reader(&mut self, mut reader: impl Read) -> io::Result<u64> {
+        let mut buf = vec![0u8; 1 << 20];
+        let mut total = 0u64;
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                return Ok(total);
+            }
+            self.update(&buf[..n]);
+            total += n as u64;
+        }
+    }
+
+    pub fn finalize(self) -> Hashes {
+        Hashes {
+            sha256: hex::encode(self.sha256.finalize()),
+            sha1: hex::encode(self.sha1.finalize()),
+            md5: hex::encode(self.md5.finalize()),
+        }
+    }
+}
+
+/// Hash a file already on disk (aria2c downloads, verification)
+pub fn hash_file(path: &Path) -> io::Result<(u64, Hashes)> {
+    let mut hasher = Hasher::new();
+    let size = hasher.update_reader(File::open(path)?)?;
+    Ok((size, hasher.finalize()))
+}
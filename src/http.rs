@@ -0,0 +1,225 @@
+/*
+    HTTP client factory shared by both scraping engines.
+    Timeouts, user agent, extra headers, cookies, HTTP version and TLS roots
+    all come from HttpConfig so every reqwest::Client is built the same way.
+*/
+
+use reqwest::cookie::Jar;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, Proxy, Url};
+use std::error::Error;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (compatible; VX-Underground-Scraper/1.0)";
+
+/// HTTP protocol preference
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// Negotiate via ALPN (HTTP/2 when the server offers it)
+    Auto,
+    /// Only speak HTTP/1.1
+    Http1,
+    /// Assume the server speaks HTTP/2 without negotiation
+    Http2,
+}
+
+impl HttpVersion {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(HttpVersion::Auto),
+            "1" | "1.1" | "http1" => Some(HttpVersion::Http1),
+            "2" | "http2" => Some(HttpVersion::Http2),
+            _ => None,
+        }
+    }
+}
+
+/// Settings applied to every client built by `build_client`
+#[derive(Clone, Debug)]
+pub struct HttpConfig {
+    /// Time allowed to establish a connection (None = no limit)
+    pub connect_timeout: Option<Duration>,
+    /// Time allowed between two reads of the response (None = no limit)
+    pub read_timeout: Option<Duration>,
+    /// Time allowed for a whole request including the body (None = no limit)
+    pub total_timeout: Option<Duration>,
+    pub user_agent: String,
+    /// Extra headers sent with every request
+    pub headers: Vec<(String, String)>,
+    /// Netscape-format cookie file (as exported by browsers or curl)
+    pub cookie_file: Option<String>,
+    pub http_version: HttpVersion,
+    /// PEM bundle of additional trusted root certificates
+    pub ca_bundle: Option<String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Some(Duration::from_secs(30)),
+            read_timeout: Some(Duration::from_secs(120)),
+            total_timeout: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            headers: Vec::new(),
+            cookie_file: None,
+            http_version: HttpVersion::Auto,
+            ca_bundle: None,
+        }
+    }
+}
+
+/// Command-line flags handled by `apply_flag`; each takes one value
+pub const FLAGS: &[&str] = &[
+    "--connect-timeout",
+    "--read-timeout",
+    "--timeout",
+    "--user-agent",
+    "--header",
+    "--cookie-file",
+    "--http-version",
+    "--ca-cert",
+];
+
+/// Apply one of the HTTP command-line flags to the configuration
+pub fn apply_flag(cfg: &mut HttpConfig, flag: &str, value: &str) -> Result<(), String> {
+    match flag {
+        "--connect-timeout" | "--read-timeout" | "--timeout" => {
+            let secs = value
+                .parse::<u64>()
+                .map_err(|_| format!("invalid {} value: {}", flag.trim_start_matches('-'), value))?;
+            let timeout = if secs == 0 { None } else { Some(Duration::from_secs(secs)) };
+            match flag {
+                "--connect-timeout" => cfg.connect_timeout = timeout,
+                "--read-timeout" => cfg.read_timeout = timeout,
+                _ => cfg.total_timeout = timeout,
+            }
+        }
+        "--user-agent" => cfg.user_agent = value.to_string(),
+        "--header" => {
+            let header = parse_header(value)
+                .ok_or_else(|| format!("invalid header (expected 'Name: value'): {}", value))?;
+            cfg.headers.push(header);
+        }
+        "--cookie-file" => cfg.cookie_file = Some(value.to_string()),
+        "--http-version" => {
+            cfg.http_version = HttpVersion::parse(value)
+                .ok_or_else(|| format!("invalid http-version: {} (use auto, 1 or 2)", value))?;
+        }
+        "--ca-cert" => cfg.ca_bundle = Some(value.to_string()),
+        _ => return Err(format!("unknown HTTP option: {}", flag)),
+    }
+    Ok(())
+}
+
+/// Parse a `Name: value` header argument
+fn parse_header(s: &str) -> Option<(String, String)> {
+    let (name, value) = s.split_once(':')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some((name.to_string(), value.trim().to_string()))
+}
+
+/// Load a Netscape cookie file into a jar.
+/// Each line is: domain, include-subdomains, path, secure, expiry, name, value (tab separated).
+fn load_cookie_jar(path: &str) -> Result<Jar, Box<dyn Error + Send + Sync>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read cookie file {}: {}", path, e))?;
+    let jar = Jar::default();
+    for line in content.lines() {
+        // curl marks HttpOnly cookies with this prefix instead of commenting them out
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            eprintln!("[!] Ignoring malformed cookie line in {}", path);
+            continue;
+        }
+        let domain = fields[0].trim_start_matches('.');
+        let scheme = if fields[3].eq_ignore_ascii_case("TRUE") { "https" } else { "http" };
+        let url: Url = match format!("{}://{}{}", scheme, domain, fields[2]).parse() {
+            Ok(u) => u,
+            Err(_) => continue,
+        };
+        let mut cookie = format!("{}={}; Path={}", fields[5], fields[6], fields[2]);
+        if fields[0].starts_with('.') || fields[1].eq_ignore_ascii_case("TRUE") {
+            cookie.push_str(&format!("; Domain={}", domain));
+        }
+        jar.add_cookie_str(&cookie, &url);
+    }
+    Ok(jar)
+}
+
+/// The same user agent, headers, cookies and CA bundle as aria2c options,
+/// so downloads handed to it look like the client's own requests
+pub fn aria_args(cfg: &HttpConfig) -> Vec<String> {
+    let mut args = vec![format!("--user-agent={}", cfg.user_agent)];
+    for (name, value) in &cfg.headers {
+        args.push(format!("--header={}: {}", name, value));
+    }
+    if let Some(path) = &cfg.cookie_file {
+        args.push(format!("--load-cookies={}", path));
+    }
+    if let Some(path) = &cfg.ca_bundle {
+        args.push(format!("--ca-certificate={}", path));
+    }
+    args
+}
+
+/// Build a client from the shared configuration, optionally through a proxy
+/// and/or bound to a local source address
+pub fn build_client(
+    cfg: &HttpConfig,
+    proxy: Option<&str>,
+    bind: Option<IpAddr>,
+) -> Result<Client, Box<dyn Error + Send + Sync>> {
+    let mut builder = Client::builder()
+        .user_agent(cfg.user_agent.clone())
+        .tcp_keepalive(Duration::from_secs(60))
+        .pool_max_idle_per_host(20)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .local_address(bind);
+    if let Some(t) = cfg.connect_timeout {
+        builder = builder.connect_timeout(t);
+    }
+    if let Some(t) = cfg.read_timeout {
+        builder = builder.read_timeout(t);
+    }
+    if let Some(t) = cfg.total_timeout {
+        builder = builder.timeout(t);
+    }
+    if !cfg.headers.is_empty() {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &cfg.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| format!("invalid header name {}: {}", name, e))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| format!("invalid value for header {}: {}", name, e))?;
+            headers.append(name, value);
+        }
+        builder = builder.default_headers(headers);
+    }
+    if let Some(path) = &cfg.cookie_file {
+        builder = builder.cookie_provider(Arc::new(load_cookie_jar(path)?));
+    }
+    match cfg.http_version {
+        HttpVersion::Auto => {}
+        HttpVersion::Http1 => builder = builder.http1_only(),
+        HttpVersion::Http2 => builder = builder.http2_prior_knowledge(),
+    }
+    if let Some(path) = &cfg.ca_bundle {
+        let pem = std::fs::read(path).map_err(|e| format!("cannot read CA bundle {}: {}", path, e))?;
+        for cert in Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    Ok(builder.build()?)
+}
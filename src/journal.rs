@@ -0,0 +1,155 @@
+/*
+    Persistent crawl journal.
+    Every directory listing, completed file and failed file is appended to a
+    JSON-lines journal in the output directory, so an interrupted run started
+    again with --resume reuses the listings instead of re-fetching every page
+    and skips the files it already completed. A run that gets to the end
+    appends a `finished` entry; a journal without one belongs to an
+    interrupted run and is not replaced unless asked to.
+*/
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Name of the journal inside the output directory
+pub const JOURNAL_FILE: &str = ".vxug-journal.jsonl";
+
+/// Raw contents of a directory page: file hrefs or subdirectory names
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Listing {
+    pub files: Vec<String>,
+    pub dirs: Vec<String>,
+    /// Date shown next to a file href, when the page has one
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub dates: HashMap<String, String>,
+    /// Size in bytes shown next to a file href, when the page has one
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub sizes: HashMap<String, u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Entry {
+    Listed { url: String, listing: Listing },
+    Completed { url: String, path: String },
+    Failed { url: String, path: String },
+    Finished,
+}
+
+#[derive(Default)]
+struct State {
+    listings: HashMap<String, Listing>,
+    completed: HashSet<String>,
+    failed: HashSet<String>,
+}
+
+pub struct Journal {
+    file: Mutex<File>,
+    state: Mutex<State>,
+}
+
+/// Entries of the journal in `root_dir`, skipping lines that do not parse
+fn read_entries(root_dir: &str) -> Vec<Entry> {
+    let content = fs::read_to_string(Path::new(root_dir).join(JOURNAL_FILE)).unwrap_or_default();
+    // a crash can leave a truncated last line behind
+    content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+}
+
+/// Whether `root_dir` holds the journal of a run that did not finish
+pub fn unfinished(root_dir: &str) -> bool {
+    read_entries(root_dir).last().is_some_and(|entry| !matches!(entry, Entry::Finished))
+}
+
+impl Journal {
+    /// Open the journal in `root_dir`. With `resume` the previous journal is
+    /// loaded and appended to; otherwise a fresh journal is started.
+    pub fn open(root_dir: &str, resume: bool) -> io::Result<Self> {
+        let path = Path::new(root_dir).join(JOURNAL_FILE);
+        let mut state = State::default();
+        let entries = if resume { read_entries(root_dir) } else { Vec::new() };
+        for entry in entries {
+            match entry {
+                Entry::Listed { url, listing } => {
+                    state.listings.insert(url, listing);
+                }
+                Entry::Completed { url, .. } => {
+                    state.failed.remove(&url);
+                    state.completed.insert(url);
+                }
+                Entry::Failed { url, .. } => {
+                    state.completed.remove(&url);
+                    state.failed.insert(url);
+                }
+                Entry::Finished => {}
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(&path)?;
+        Ok(Self { file: Mutex::new(file), state: Mutex::new(state) })
+    }
+
+    fn append(&self, entry: &Entry) {
+        let mut line = serde_json::to_string(entry).unwrap_or_default();
+        line.push('\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            eprintln!("[!] Failed to write to {}: {}", JOURNAL_FILE, e);
+        }
+    }
+
+    /// Listing recorded for `url` by this or a previous run
+    pub fn listing(&self, url: &str) -> Option<Listing> {
+        self.state.lock().unwrap().listings.get(url).cloned()
+    }
+
+    pub fn record_listing(&self, url: &str, listing: &Listing) {
+        self.state.lock().unwrap().listings.insert(url.to_string(), listing.clone());
+        self.append(&Entry::Listed { url: url.to_string(), listing: listing.clone() });
+    }
+
+    pub fn record_completed(&self, url: &str, path: &str) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.failed.remove(url);
+            state.completed.insert(url.to_string());
+        }
+        self.append(&Entry::Completed { url: url.to_string(), path: path.to_string() });
+    }
+
+    pub fn record_failed(&self, url: &str, path: &str) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.completed.remove(url);
+            state.failed.insert(url.to_string());
+        }
+        self.append(&Entry::Failed { url: url.to_string(), path: path.to_string() });
+    }
+
+    /// Whether `url` was downloaded completely by this or a previous run
+    pub fn completed(&self, url: &str) -> bool {
+        self.state.lock().unwrap().completed.contains(url)
+    }
+
+    /// Whether the last attempt at `url` failed
+    pub fn failed(&self, url: &str) -> bool {
+        self.state.lock().unwrap().failed.contains(url)
+    }
+
+    /// Mark the run as finished, so the next run may start a fresh journal
+    pub fn finish(&self) {
+        self.append(&Entry::Finished);
+    }
+
+    /// Number of listings, completed files and failed files known to the journal
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.listings.len(), state.completed.len(), state.failed.len())
+    }
+}
@@ -0,0 +1,7 @@
+/*
+    Code shared by the vxug-scraper and adv-scrap-x100 binaries:
+    the HTTP client factory and the crawl journal.
+*/
+
+pub mod http;
+pub mod journal;
@@ -5,19 +5,51 @@
 
 use futures::stream::StreamExt;
 use regex::Regex;
-use reqwest::{Client, header::{ACCEPT_ENCODING, CONTENT_LENGTH}};
+use reqwest::{Client, StatusCode, header::{ACCEPT_ENCODING, CONTENT_LENGTH, RANGE}};
 use scraper::{Html, Selector};
 use std::collections::HashSet;
-use std::error::Error;
 use std::fs::{self, File};
 use std::io::Write;
-use std::sync::Arc;
-use tokio::time::{Duration, sleep};
+use std::sync::{Arc, OnceLock};
+use tokio::time::{Duration, Instant, sleep};
 use std::sync::atomic::{AtomicU64, AtomicUsize, AtomicBool, Ordering};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use tokio::process::Command;
 use std::path::{Path, PathBuf};
 
+mod archive;
+mod budget;
+mod catalog;
+mod confine;
+mod harden;
+mod hash;
+mod dedup;
+mod error;
+mod estimate;
+mod extract;
+mod manifest;
+mod metadata;
+mod pool;
+mod retry;
+mod sanitize;
+mod shutdown;
+mod space;
+mod stats;
+mod store;
+mod throttle;
+mod validate;
+mod verify;
+use catalog::{Catalog, FileStatus};
+use error::{Error, IoContext};
+use hash::{Hasher, Hashes};
+use vxug_scraper::{http, journal};
+use http::HttpConfig;
+use journal::{Journal, Listing};
+use manifest::Manifest;
+use pool::{ClientPool, Strategy};
+use retry::Deferred;
+use stats::STATS;
+
 /// Global rate limit in seconds between HTTP requests (0 = no limit)
 static RATE_LIMIT_SECS: AtomicU64 = AtomicU64::new(0);
 /// Maximum number of concurrent download tasks per directory (0 = unlimited)
@@ -26,6 +58,20 @@ static CONCURRENCY: AtomicUsize = AtomicUsize::new(0);
 static USE_ARIA: AtomicBool = AtomicBool::new(false);
 /// Only invoke aria2c for files larger than this threshold in bytes (0 = always when -a)
 static ARIA_THRESHOLD: AtomicU64 = AtomicU64::new(0);
+/// Minimum transfer speed in bytes per second before a download counts as stalled (0 = disabled)
+static MIN_SPEED: AtomicU64 = AtomicU64::new(10 * 1024);
+/// Window in seconds over which the minimum speed is measured (0 = disabled)
+static STALL_WINDOW_SECS: AtomicU64 = AtomicU64::new(60);
+/// Immediate resume attempts after a stall before the file is deferred
+const STALL_RETRIES: u32 = 3;
+/// Deepest directory the crawl descends into, counted in remote path segments
+const MAX_DEPTH: usize = 32;
+/// Crawl journal in the output directory, opened at startup
+static JOURNAL: OnceLock<Journal> = OnceLock::new();
+/// SQLite catalog of directories and files, opened when --catalog is given
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+/// Hash manifest of completed downloads
+static MANIFEST: OnceLock<Manifest> = OnceLock::new();
 /// Scan the given directory recursively for .aria2 control files
 fn collect_aria2_files(dir: &Path, out: &mut Vec<PathBuf>) {
     if let Ok(entries) = std::fs::read_dir(dir) {
@@ -40,8 +86,31 @@ fn collect_aria2_files(dir: &Path, out: &mut Vec<PathBuf>) {
     }
 }
 
+/// Run aria2c with its share of --limit-rate, waiting out pause windows. When
+/// the share changes it is stopped and started again with `-c`, continuing
+/// from its control file. Returns None when the transfer was interrupted.
+async fn run_aria(name: &str, command: impl Fn() -> Command) -> std::io::Result<Option<std::process::ExitStatus>> {
+    let share = throttle::AriaShare::new();
+    loop {
+        throttle::wait_if_paused().await;
+        if shutdown::requested() {
+            return Ok(None);
+        }
+        let (rate, limit) = share.claim();
+        let mut cmd = command();
+        if let Some(limit) = limit {
+            cmd.arg(format!("--max-download-limit={}", limit));
+        }
+        match shutdown::run_child(cmd, share.changed(rate)).await? {
+            shutdown::ChildEnd::Exited(status) => return Ok(Some(status)),
+            shutdown::ChildEnd::Interrupted => return Ok(None),
+            shutdown::ChildEnd::Restart => println!("[*] Download rate changed, restarting aria2c for {}", name),
+        }
+    }
+}
+
 /// Resume any pending aria2 downloads found under root_dir
-async fn resume_pending_downloads(root_dir: &str) {
+async fn resume_pending_downloads(root_dir: &str, pool: &ClientPool) {
     let root = Path::new(root_dir);
     let mut control_files = Vec::new();
     println!("[*] Scanning '{}' for pending .aria2 control files...", root_dir);
@@ -52,6 +121,9 @@ async fn resume_pending_downloads(root_dir: &str) {
     }
     println!("[*] Resuming {} pending aria2 downloads...", control_files.len());
     for control in control_files {
+        if shutdown::requested() {
+            break;
+        }
         // derive original file path (remove .aria2 extension)
         let mut file_path = control.clone();
         file_path.set_extension("");
@@ -62,17 +134,29 @@ async fn resume_pending_downloads(root_dir: &str) {
         if let Some(line) = content.lines().find(|l| l.starts_with("uri=")) {
             let url = &line[4..];
             println!("[*] Resuming download {} from {}", name, url);
-            let mut cmd = Command::new("aria2c");
-            cmd.arg("-c").arg("--auto-file-renaming=false");
-            cmd.arg("-d").arg(dir).arg("-o").arg(name).arg(url);
-            if let Ok(opts) = std::env::var("ARIA_OPTS") {
-                for token in opts.split_whitespace() {
-                    cmd.arg(token);
+            let route = pool.current();
+            let command = || {
+                let mut cmd = Command::new("aria2c");
+                cmd.arg("-c").arg("--auto-file-renaming=false");
+                cmd.arg("-d").arg(dir).arg("-o").arg(name).arg(url);
+                cmd.args(pool.aria_args());
+                if let Some(proxy) = route.aria_proxy() {
+                    cmd.arg(format!("--all-proxy={}", proxy));
                 }
-            }
-            match cmd.status().await {
-                Ok(s) if s.success() => println!("[+] Resumed and completed {}", name),
-                Ok(s) => eprintln!("[!] aria2c exited {} while resuming {}", s, name),
+                if let Some(bind) = route.bind {
+                    cmd.arg(format!("--interface={}", bind));
+                }
+                if let Ok(opts) = std::env::var("ARIA_OPTS") {
+                    for token in opts.split_whitespace() {
+                        cmd.arg(token);
+                    }
+                }
+                cmd
+            };
+            match run_aria(name, command).await {
+                Ok(Some(s)) if s.success() => println!("[+] Resumed and completed {}", name),
+                Ok(Some(s)) => eprintln!("[!] aria2c exited {} while resuming {}", s, name),
+                Ok(None) => eprintln!("[!] Interrupted while resuming {}", name),
                 Err(e) => eprintln!("[!] Failed to start aria2c for {}: {}", name, e),
             }
         } else {
@@ -86,6 +170,10 @@ fn print_help() {
     println!();
     println!("Usage:");
     println!("  vxug-scraper [OPTIONS] [START_PATH]");
+    println!("  vxug-scraper catalog [OPTIONS] [QUERY]   Query the catalog (see catalog --help)");
+    println!("  vxug-scraper verify [OPTIONS]            Check the mirror against the manifest (see verify --help)");
+    println!("  vxug-scraper rebuild-view [OPTIONS]      Recreate the --layout cas symlink view from the catalog");
+    println!("  vxug-scraper harden [OPTIONS]            Make an existing mirror sample-safe (see harden --help)");
     println!();
     println!("Options:");
     println!("  -o, --output-dir <DIR>         Set output directory (default: Downloads)");
@@ -94,30 +182,88 @@ fn print_help() {
     println!("  -a, --aria                     Use aria2c for downloads");
     println!("  --aria-opts <OPTS>             Extra options to pass to aria2c");
     println!("  --aria-threshold <BYTES>       Only use aria2c for files larger than this size");
+    println!("  --min-speed <BYTES/SEC>        Abort and resume transfers slower than this (default: 10240, 0=off)");
+    println!("  --stall-window <SECONDS>       Window over which --min-speed is measured (default: 60)");
+    println!("  --limit-rate <RATE>            Global download cap, e.g. 500K or 2M per second (default: unlimited)");
+    println!("  --schedule <SPEC>              Time-of-day windows, e.g. \"22:00-06:00=full,09:00-12:00=pause\"");
+    println!("  --schedule-file <FILE>         Read the schedule from a file, re-read whenever it changes");
+    println!("  --estimate                     Crawl and report sizes per collection instead of downloading");
+    println!("  --budget <SIZE>                Only download what fits in this much space, e.g. 500G");
+    println!("  --select <ORDER>               What --budget fills first: newest (default), smallest or priority");
+    println!("  --priority <COLLECTIONS>       Comma-separated collections in priority order for --select priority");
+    println!("  --min-free <SIZE>              Free space to keep on the output volume (default: 1G, 0=off)");
+    println!("  --on-low-space <ACTION>        pause (default): wait until space is freed, or stop the run");
+    println!("  --resume                       Continue an interrupted run using the crawl journal");
+    println!("  --fresh                        Start a new journal even if the last run was interrupted");
+    println!("  --manifest-format <FMT>        Hash manifest: json (default), sha256sums or both");
+    println!("  --layout <LAYOUT>              tree (default) or cas: store files by SHA-256 with a symlink view");
+    println!("  --dedup <MODE>                 Replace files whose content is already present: hardlink, reflink or symlink");
+    println!("  --inspect                      List archive contents and inner hashes into the manifest");
+    println!("  --password <PW>                Archive password to try; repeatable (default: infected)");
+    println!("  --password-file <FILE>         Read archive passwords from a file, one per line");
+    println!("  --sample-safe                  Make completed files read-only and non-executable, mark sample directories");
+    println!("  --extract <DIR>                Unpack downloaded archives into a quarantine directory");
+    println!("  --extract-max-size <SIZE>      Largest decompressed total per archive (default: 4G)");
+    println!("  --extract-max-files <N>        Most files per archive (default: 10000)");
+    println!("  --extract-max-ratio <N>        Largest decompressed/archive size ratio (default: 200)");
+    println!("  --rename-executables           Save extracted executables as <name>.malware");
+    println!("  --meta-sidecar                 Write <file>.meta.json with URL, headers, time, size, hashes and downloader");
+    println!("  --catalog                      Record directories and files in <output-dir>/catalog.sqlite");
+    println!("  --catalog-file <FILE>          Record the catalog in this SQLite database instead");
+    println!("  --retry-cooldown <SECONDS>     Wait before each deferred retry round (default: 30)");
+    println!("  --retry-rounds <NUM>           Deferred retry rounds at the end of the run (default: 2)");
+    println!("  --proxy <URL>                  Route requests through a proxy (http://, https://, socks5://); repeatable");
+    println!("  --proxy-file <FILE>            Read proxy URLs from a file, one per line");
+    println!("  --bind <ADDR>                  Send requests from a local IPv4/IPv6 address; repeatable");
+    println!("  --rotate <STRATEGY>            Route selection: failover (default) or round-robin");
+    println!("  --route-cooldown <SECONDS>     Take a rate-limited route out of use (default: 300)");
+    println!("  --connect-timeout <SECONDS>    Connection timeout (default: 30, 0=none)");
+    println!("  --read-timeout <SECONDS>       Timeout between reads of a response (default: 120, 0=none)");
+    println!("  --timeout <SECONDS>            Total timeout per request incl. body (default: 0=none)");
+    println!("  --user-agent <UA>              User-Agent header to send");
+    println!("  --header <NAME: VALUE>         Extra header to send with every request; repeatable");
+    println!("  --cookie-file <FILE>           Load cookies from a Netscape-format cookie file");
+    println!("  --http-version <VER>           auto (default), 1 (HTTP/1.1 only) or 2 (HTTP/2 prior knowledge)");
+    println!("  --ca-cert <FILE>               Trust the root certificates in this PEM bundle");
     println!("  -h, --help                     Print this help message and exit");
     println!();
 }
-/// Pause execution waiting for network switching and resume after countdown
-fn pause_for_proxy_change() {
-    eprintln!("[!] Rate limit or network error detected.");
-    eprintln!("[*] Press Enter to start retry countdown...");
-    let mut _enter = String::new();
-    std::io::stdin().read_line(&mut _enter).unwrap();
-    eprintln!("[*] Waiting for network switching to take effect...");
-    for remaining in (1..=10).rev() {
-        eprint!("\r[*] Retrying in {} seconds...", remaining);
-        std::io::stdout().flush().unwrap();
-        std::thread::sleep(std::time::Duration::from_secs(1));
-    }
-    eprintln!("\r[*] Resuming now...");
-}
-
-
 // async fn => 
 
 #[tokio::main]
-async fn main() -> Result<(), reqwest::Error> {
+async fn main() -> error::Result<()> {
     let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("catalog") {
+        if let Err(e) = catalog::run_command(&args[2..]) {
+            eprintln!("Error: catalog query failed: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("rebuild-view") {
+        if let Err(e) = store::run_command(&args[2..]) {
+            eprintln!("Error: rebuild-view failed: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("harden") {
+        if let Err(e) = harden::run_command(&args[2..]) {
+            eprintln!("Error: harden failed: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("verify") {
+        match verify::run_command(&args[2..]) {
+            Ok(0) => return Ok(()),
+            Ok(_) => std::process::exit(2),
+            Err(e) => {
+                eprintln!("Error: verify failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
     if args.iter().any(|a| a == "-h" || a == "--help") {
         print_help();
         return Ok(());
@@ -126,6 +272,18 @@ async fn main() -> Result<(), reqwest::Error> {
     let base_url = "https://vx-underground.org";
     let mut start_path: Option<String> = None;
     let mut root_dir = String::from("Downloads");
+    let mut proxies: Vec<String> = Vec::new();
+    let mut binds: Vec<std::net::IpAddr> = Vec::new();
+    let mut strategy = Strategy::Failover;
+    let mut http_config = HttpConfig::default();
+    let mut resume = false;
+    let mut fresh = false;
+    let mut catalog_path: Option<PathBuf> = None;
+    let mut use_catalog = false;
+    let mut manifest_format = manifest::Format::Json;
+    let mut cas_layout = false;
+    let mut extract_dir: Option<PathBuf> = None;
+    let mut estimating = false;
     // default no concurrency limit (0 = unlimited)
 
     let mut i = 1;
@@ -187,359 +345,1606 @@ async fn main() -> Result<(), reqwest::Error> {
                 ARIA_THRESHOLD.store(th, Ordering::Relaxed);
                 i += 2;
             }
-            other => {
-                if start_path.is_some() {
-                    eprintln!("Warning: multiple paths specified, using first: {}", other);
-                } else {
-                    start_path = Some(other.to_string());
+            "--min-speed" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
                 }
-                i += 1;
+                let speed = args[i + 1].parse::<u64>().unwrap_or_else(|_| {
+                    eprintln!("Error: invalid min-speed value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                MIN_SPEED.store(speed, Ordering::Relaxed);
+                i += 2;
             }
-        }
-    }
-
-    banner();
-
-    fs::create_dir_all(&root_dir).expect("Unable to create root directory");
-
-    let mut start_url = format!("{}/", base_url);
-    if let Some(path) = &start_path {
-        start_url = format!("{}/{}", base_url, path);
-        match check_url(&start_url) {
-            Ok(true) => println!("[+] URL is reachable: {} - STATUS {}", start_url, 200),
-            Ok(false) => eprintln!("[!] URL not reachable: {}", start_url),
-            Err(e) => eprintln!("Error while checking URL: {}", e),
-        }
-        println!("[+] Downloading collection: {}", path);
-    } else {
-        println!("[+] No parameter detected. Starting to download all collections...");
-    }
-
-    println!("[*] Press Enter to Start Processing =>");
-    std::io::stdin().read_line(&mut String::new()).unwrap();
-    std::io::stdout().flush().unwrap();
-    // show concurrency limit if set
-    let concurrency_limit = CONCURRENCY.load(Ordering::Relaxed);
-    if concurrency_limit > 0 {
-        println!("[*] Concurrency limit per directory: {}", concurrency_limit);
-    }
-    if USE_ARIA.load(Ordering::Relaxed) {
-        println!("[*] Using aria2c for downloads");
-        // First, resume any pending aria2 downloads before continuing
-        resume_pending_downloads(&root_dir).await;
-    }
-
-    let client = Arc::new(Client::builder().build()?);
-    let mp = Arc::new(MultiProgress::new());
-    println!("Starting scrape at URL: {}", start_url);
-
-    let mut visited = HashSet::new();
-    let skip_segments: Vec<String> = if let Some(path) = &start_path {
-        path.split('/').map(|s| s.to_string()).collect()
-    } else {
-        Vec::new()
-    };
-    scrape_directory(client, base_url, &start_url, &root_dir, &mut visited, &skip_segments, mp.clone()).await?;
-
-    println!("Scraping and downloading complete!");
-    Ok(())
-}
-
-async fn scrape_directory(
-    client: Arc<Client>,
-    base_url: &str,
-    url: &str,
-    dir: &str,
-    visited: &mut HashSet<String>,
-    skip_segments: &[String],
-    mp: Arc<MultiProgress>,
-) -> Result<(), reqwest::Error> {
-    println!("Processing URL: {} | Saving to directory: {}", url, dir);
-
-    let current_dir = url
-        .split('/')
-        .filter(|s| !s.is_empty())
-        .last()
-        .unwrap_or("")
-        .to_string();
-
-    visited.insert(current_dir.clone());
-    println!("Visited directories: {:?}", visited);
-
-    // fetch page with rate-limit and retry up to 3 times on rate-limit or network errors
-    let mut attempts = 0;
-    let max_attempts = 3;
-    let response_text = loop {
-        attempts += 1;
-        let rl = RATE_LIMIT_SECS.load(Ordering::Relaxed);
-        if rl > 0 {
-            sleep(Duration::from_secs(rl)).await;
-        }
-        match client.get(url).send().await {
-            Ok(resp) => {
-                let status = resp.status();
-                if !status.is_success() {
-                    eprintln!("[!] Attempt {}/{}: HTTP status {} received from {}.", attempts, max_attempts, status, url);
-                } else {
-                    match resp.text().await {
-                        Ok(body) => break body,
-                        Err(e) => {
-                            eprintln!("[!] Attempt {}/{}: Error reading body from {}: {}.", attempts, max_attempts, url, e);
-                        }
-                    }
+            "--stall-window" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
                 }
+                let secs = args[i + 1].parse::<u64>().unwrap_or_else(|_| {
+                    eprintln!("Error: invalid stall-window value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                STALL_WINDOW_SECS.store(secs, Ordering::Relaxed);
+                i += 2;
             }
-            Err(e) => {
-                eprintln!("[!] Attempt {}/{}: HTTP request to {} failed: {}.", attempts, max_attempts, url, e);
+            "--limit-rate" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let rate = throttle::parse_size(&args[i + 1]).unwrap_or_else(|| {
+                    eprintln!("Error: invalid limit-rate value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                throttle::LIMIT_RATE.store(rate, Ordering::Relaxed);
+                i += 2;
             }
-        }
-        if attempts >= max_attempts {
-            eprintln!("[!] Failed to fetch {} after {} attempts. Skipping...", url, max_attempts);
-            return Ok(());
-        }
-        pause_for_proxy_change();
-    };
-    let document = Html::parse_document(&response_text);
-
-    // check for .pdf or .zip files
-    let link_selector =
-        Selector::parse(r#"a[href$=".pdf"], a[href$=".zip"], a[href$=".7z"]"#).unwrap();
-    let links: Vec<(String, String)> = document
-        .select(&link_selector)
-        .filter_map(|link| {
-            let href = link.value().attr("href")?.to_string();
-            let name = href.split('/').last()?.to_string();
-            let sanitized_name = Regex::new(r"[<>:/\\|?*]")
-                .unwrap()
-                .replace_all(&name, "_")
-                .to_string();
-            Some((sanitized_name, href))
-        })
-        .collect();
-
-    if !links.is_empty() {
-        println!(
-            "Found {} files at {}: {:?}",
-            links.len(),
-            url,
-            links.iter().map(|(name, _)| name).collect::<Vec<_>>()
-        );
-        // bounded concurrency per directory via stream buffer_unordered
-        let concurrency = CONCURRENCY.load(Ordering::Relaxed) as usize;
-        let max_concurrency = if concurrency == 0 { links.len() } else { concurrency };
-        futures::stream::iter(links.into_iter().map(|(name, href)| {
-            let client = Arc::clone(&client);
-            let mp = mp.clone();
-            let base_url = base_url.to_string();
-            let dir = dir.to_string();
-            async move {
-                let file_path = format!("{}/{}", dir, name);
-                let aria2_control = format!("{}.aria2", file_path);
-                if Path::new(&file_path).exists() {
-                    if USE_ARIA.load(Ordering::Relaxed) && Path::new(&aria2_control).exists() {
-                        println!("Resuming incomplete download for {} (found .aria2)", name);
-                    } else {
-                        println!("Skipping {}: already exists at {}", name, file_path);
-                        return;
-                    }
+            "--schedule" | "--schedule-file" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
                 }
-                let file_url = if href.starts_with("http") {
-                    href
+                let result = if args[i] == "--schedule" {
+                    throttle::set_schedule(&args[i + 1])
                 } else {
-                    format!("{}{}", base_url, href)
+                    throttle::set_schedule_file(&args[i + 1])
                 };
-                println!("Downloading {} to {}", name, file_path);
-                // Determine per-file if aria2c should be used based on threshold
-                let mut use_aria_for_file = USE_ARIA.load(Ordering::Relaxed);
-                let threshold = ARIA_THRESHOLD.load(Ordering::Relaxed);
-                if use_aria_for_file && threshold > 0 {
-                    match client.head(&file_url).send().await {
-                        Ok(resp) => {
-                            if let Some(len_header) = resp.headers().get(CONTENT_LENGTH) {
-                                if let Ok(len_str) = len_header.to_str() {
-                                    if let Ok(len) = len_str.parse::<u64>() {
-                                        if len <= threshold {
-                                            use_aria_for_file = false;
-                                        }
-                                    } else {
-                                        use_aria_for_file = false;
-                                    }
-                                } else {
-                                    use_aria_for_file = false;
-                                }
-                            } else {
-                                use_aria_for_file = false;
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("[!] Failed to HEAD {}: {}, falling back to HTTP download", file_url, e);
-                            use_aria_for_file = false;
-                        }
-                    }
+                if let Err(e) = result {
+                    eprintln!("Error: invalid schedule: {}", e);
+                    std::process::exit(1);
                 }
-                let mut success = false;
-                for attempt in 1..=3 {
-                    if use_aria_for_file {
-                        let mut cmd = Command::new("aria2c");
-                        cmd.arg("-c")
-                            .arg("--auto-file-renaming=false")
-                            .arg("-d").arg(&dir)
-                            .arg("-o").arg(&name);
-                        // Apply aria2c options: user-provided or default for higher concurrency
-                        let aria_opts = std::env::var("ARIA_OPTS").unwrap_or_else(|_| "-x 4 -s 4".to_string());
-                        for token in aria_opts.split_whitespace() {
-                            cmd.arg(token);
-                        }
-                        cmd.arg(&file_url);
-                        let status = cmd.status().await;
-                        match status {
-                            Ok(s) if s.success() => {
-                                println!("Saved {} to {}", name, file_path);
-                                success = true;
-                            }
-                            Ok(s) => eprintln!("Attempt {}/3: aria2c exited with {} for {}", attempt, s, name),
-                            Err(e) => eprintln!("Attempt {}/3: failed to start aria2c for {}: {}", attempt, name, e),
-                        }
-                    } else {
-                        match download_file(&client, &file_url, &file_path, mp.clone()).await {
-                            Ok(_) => {
-                                println!("Saved {} to {}", name, file_path);
-                                success = true;
-                            }
-                            Err(e) => eprintln!("Attempt {}/3 failed for {}: {}", attempt, name, e),
-                        }
-                    }
-                    if success {
-                        break;
-                    }
-                    if attempt < 3 {
-                        if attempt == 1 {
-                            pause_for_proxy_change();
-                        } else {
-                            eprintln!("[*] Waiting for network switching to take effect...");
-                            for remaining in (1..=10).rev() {
-                                eprint!("\r[*] Retrying in {} seconds...", remaining);
-                                std::io::stdout().flush().unwrap();
-                                sleep(Duration::from_secs(1)).await;
-                            }
-                            eprintln!("\r[*] Resuming now...");
-                        }
+                i += 2;
+            }
+            "--resume" => {
+                resume = true;
+                i += 1;
+            }
+            "--fresh" => {
+                fresh = true;
+                i += 1;
+            }
+            "--manifest-format" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                manifest_format = manifest::Format::parse(&args[i + 1]).unwrap_or_else(|| {
+                    eprintln!("Error: invalid manifest format: {} (use json, sha256sums or both)", args[i + 1]);
+                    std::process::exit(1);
+                });
+                i += 2;
+            }
+            "--layout" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                cas_layout = match args[i + 1].as_str() {
+                    "tree" => false,
+                    "cas" => true,
+                    other => {
+                        eprintln!("Error: invalid layout: {} (use tree or cas)", other);
+                        std::process::exit(1);
                     }
+                };
+                i += 2;
+            }
+            "--dedup" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
                 }
-                if !success {
-                    eprintln!("[!] Failed to download {} after 3 attempts, skipping.", name);
+                let mode = dedup::Mode::parse(&args[i + 1]).unwrap_or_else(|| {
+                    eprintln!("Error: invalid dedup mode: {} (use hardlink, reflink or symlink)", args[i + 1]);
+                    std::process::exit(1);
+                });
+                let _ = dedup::MODE.set(mode);
+                i += 2;
+            }
+            "--extract" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
                 }
+                extract_dir = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
             }
-        }))
-        .buffer_unordered(max_concurrency)
-        .for_each(|_| async {})
-        .await;
-    } else {
-
-        let category_selector =
-            Selector::parse(r#"div.cursor-pointer span.text-white.text-xs.truncate"#).unwrap();
-        let mut categories: Vec<String> = document
-            .select(&category_selector)
-            .map(|e| e.text().collect::<Vec<_>>().join("").trim().to_string())
-            .collect();
-
-        categories.retain(|category|
-            !visited.contains(category)
-            && category != &current_dir
-            && !skip_segments.contains(category)
-        );
-
-        if categories.is_empty() {
-            println!("No subdirectories found at {}", url);
-            visited.remove(&current_dir);
-            return Ok(());
-        }
-
-        println!("Found subdirectories at {}: {:?}", url, categories);
-        for category in categories {
-            let category_url = format!("{}/{}", url.trim_end_matches('/'), category);
-            let category_dir = format!("{}/{}", dir, category);
-            fs::create_dir_all(&category_dir).expect("Unable to create category directory");
-
-            let recursive_call = Box::pin(scrape_directory(
-                Arc::clone(&client),
-                base_url,
-                &category_url,
-                &category_dir,
-                visited,
-                skip_segments,
-                mp.clone(),
-            ));
-
-            if let Err(e) = recursive_call.await {
-                eprintln!("Failed to scrape {}: {}", category_url, e);
+            "--estimate" => {
+                estimating = true;
+                i += 1;
             }
-        }
-    }
-
-    // remove the current dir ...
-    visited.remove(&current_dir);
-    Ok(())
-}
-
-async fn download_file(client: &Client, url: &str, file_path: &str, mp: Arc<MultiProgress>) -> Result<(), reqwest::Error> {
-    let rl = RATE_LIMIT_SECS.load(Ordering::Relaxed);
-    if rl > 0 {
-        sleep(Duration::from_secs(rl)).await;
-    }
-    // request raw stream without content decoding to avoid loading large bodies into memory
-    let mut resp = client.get(url)
-        .header(ACCEPT_ENCODING, "identity")
-        .send()
-        .await?
-        .error_for_status()?;
-    let total_size = resp.content_length().unwrap_or(0);
-    let show_progress = total_size > 50 * 1024 * 1024;
-
-    if let Some(parent) = std::path::Path::new(file_path).parent() {
-        fs::create_dir_all(parent).unwrap();
-    }
-    let mut file = File::create(file_path).unwrap();
-    let pb = if show_progress {
-        let pb = mp.add(ProgressBar::new(total_size));
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{prefix} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent:.2}%)")
-                .unwrap()
-                .progress_chars("=>-")
-        );
-        pb.set_prefix(file_path.to_string());
-        Some(pb)
-    } else {
-        None
-    };
-    while let Some(chunk) = resp.chunk().await? {
-        file.write_all(&chunk).unwrap();
-        if let Some(pb) = &pb {
-            pb.inc(chunk.len() as u64);
-        }
-    }
-    if let Some(pb) = pb {
-        pb.finish_with_message("done");
-    }
-    Ok(())
-}
-
-fn check_url(url: &str) -> Result<bool, Box<dyn Error>> {
-    let response = reqwest::blocking::get(url)?;
-    Ok(response.status().is_success())
-}
-
-fn banner() {
-    println!("
-██╗   ██╗██╗  ██╗      ██╗   ██╗ ██████╗     ███████╗ ██████╗██████╗  █████╗ ██████╗ ███████╗██████╗ 
-██║   ██║╚██╗██╔╝      ██║   ██║██╔════╝     ██╔════╝██╔════╝██╔══██╗██╔══██╗██╔══██╗██╔════╝██╔══██╗
-██║   ██║ ╚███╔╝ █████╗██║   ██║██║  ███╗    ███████╗██║     ██████╔╝███████║██████╔╝█████╗  ██████╔╝
-╚██╗ ██╔╝ ██╔██╗ ╚════╝██║   ██║██║   ██║    ╚════██║██║     ██╔══██╗██╔══██║██╔═══╝ ██╔══╝  ██╔══██╗
- ╚████╔╝ ██╔╝ ██╗      ╚██████╔╝╚██████╔╝    ███████║╚██████╗██║  ██║██║  ██║██║     ███████╗██║  ██║
+            "--budget" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let size = throttle::parse_size(&args[i + 1]).unwrap_or_else(|| {
+                    eprintln!("Error: invalid budget value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                budget::BUDGET.store(size, Ordering::Relaxed);
+                i += 2;
+            }
+            "--select" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let selection = budget::Selection::parse(&args[i + 1]).unwrap_or_else(|| {
+                    eprintln!("Error: invalid select value: {} (expected newest, smallest or priority)", args[i + 1]);
+                    std::process::exit(1);
+                });
+                let _ = budget::SELECT.set(selection);
+                i += 2;
+            }
+            "--priority" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let collections = args[i + 1].split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect();
+                let _ = budget::PRIORITY.set(collections);
+                i += 2;
+            }
+            "--min-free" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let size = throttle::parse_size(&args[i + 1]).unwrap_or_else(|| {
+                    eprintln!("Error: invalid min-free value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                space::MIN_FREE.store(size, Ordering::Relaxed);
+                i += 2;
+            }
+            "--on-low-space" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let action = space::Action::parse(&args[i + 1]).unwrap_or_else(|| {
+                    eprintln!("Error: invalid on-low-space value: {} (expected pause or stop)", args[i + 1]);
+                    std::process::exit(1);
+                });
+                let _ = space::ACTION.set(action);
+                i += 2;
+            }
+            "--extract-max-size" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let size = throttle::parse_size(&args[i + 1]).unwrap_or_else(|| {
+                    eprintln!("Error: invalid extract-max-size value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                extract::MAX_TOTAL_BYTES.store(size, Ordering::Relaxed);
+                i += 2;
+            }
+            "--extract-max-files" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let n = args[i + 1].parse::<usize>().unwrap_or_else(|_| {
+                    eprintln!("Error: invalid extract-max-files value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                extract::MAX_FILES.store(n, Ordering::Relaxed);
+                i += 2;
+            }
+            "--extract-max-ratio" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let ratio = args[i + 1].parse::<u64>().unwrap_or_else(|_| {
+                    eprintln!("Error: invalid extract-max-ratio value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                extract::MAX_RATIO.store(ratio, Ordering::Relaxed);
+                i += 2;
+            }
+            "--rename-executables" => {
+                extract::RENAME_EXECUTABLES.store(true, Ordering::Relaxed);
+                i += 1;
+            }
+            "--sample-safe" => {
+                harden::SAMPLE_SAFE.store(true, Ordering::Relaxed);
+                i += 1;
+            }
+            "--inspect" => {
+                archive::INSPECT.store(true, Ordering::Relaxed);
+                i += 1;
+            }
+            "--password" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                archive::add_password(&args[i + 1]);
+                i += 2;
+            }
+            "--password-file" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                if let Err(e) = archive::load_password_file(&args[i + 1]) {
+                    eprintln!("Error: cannot read password file {}: {}", args[i + 1], e);
+                    std::process::exit(1);
+                }
+                i += 2;
+            }
+            "--meta-sidecar" => {
+                metadata::SIDECARS.store(true, Ordering::Relaxed);
+                i += 1;
+            }
+            "--catalog" => {
+                use_catalog = true;
+                i += 1;
+            }
+            "--catalog-file" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                catalog_path = Some(PathBuf::from(&args[i + 1]));
+                use_catalog = true;
+                i += 2;
+            }
+            "--retry-cooldown" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let secs = args[i + 1].parse::<u64>().unwrap_or_else(|_| {
+                    eprintln!("Error: invalid retry-cooldown value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                retry::RETRY_COOLDOWN_SECS.store(secs, Ordering::Relaxed);
+                i += 2;
+            }
+            "--retry-rounds" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let rounds = args[i + 1].parse::<u64>().unwrap_or_else(|_| {
+                    eprintln!("Error: invalid retry-rounds value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                retry::RETRY_ROUNDS.store(rounds, Ordering::Relaxed);
+                i += 2;
+            }
+            "--proxy" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                proxies.push(args[i + 1].clone());
+                i += 2;
+            }
+            "--proxy-file" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let list = pool::read_proxy_file(&args[i + 1]).unwrap_or_else(|e| {
+                    eprintln!("Error: cannot read proxy file {}: {}", args[i + 1], e);
+                    std::process::exit(1);
+                });
+                proxies.extend(list);
+                i += 2;
+            }
+            "--bind" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let addr = args[i + 1].parse::<std::net::IpAddr>().unwrap_or_else(|_| {
+                    eprintln!("Error: invalid bind address: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                binds.push(addr);
+                i += 2;
+            }
+            "--rotate" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                strategy = Strategy::parse(&args[i + 1]).unwrap_or_else(|| {
+                    eprintln!("Error: invalid rotate strategy: {} (use failover or round-robin)", args[i + 1]);
+                    std::process::exit(1);
+                });
+                i += 2;
+            }
+            "--route-cooldown" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                let secs = args[i + 1].parse::<u64>().unwrap_or_else(|_| {
+                    eprintln!("Error: invalid route-cooldown value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                pool::ROUTE_COOLDOWN_SECS.store(secs, Ordering::Relaxed);
+                i += 2;
+            }
+            flag if http::FLAGS.contains(&flag) => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                if let Err(e) = http::apply_flag(&mut http_config, flag, &args[i + 1]) {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+                i += 2;
+            }
+            other => {
+                if start_path.is_some() {
+                    eprintln!("Warning: multiple paths specified, using first: {}", other);
+                } else {
+                    start_path = Some(other.to_string());
+                }
+                i += 1;
+            }
+        }
+    }
+
+    let budget = budget::BUDGET.load(Ordering::Relaxed);
+    let selection = match budget::SELECT.get() {
+        Some(&selection) => selection,
+        None if budget::PRIORITY.get().is_some() => budget::Selection::Priority,
+        None => budget::Selection::Newest,
+    };
+    if budget > 0 && estimating {
+        eprintln!("Error: --budget and --estimate cannot be combined");
+        std::process::exit(1);
+    }
+    if selection == budget::Selection::Priority && budget::PRIORITY.get().is_none() {
+        eprintln!("Error: --select priority requires --priority <COLLECTIONS>");
+        std::process::exit(1);
+    }
+    // with a budget the whole tree is measured before anything is chosen
+    estimate::ENABLED.store(estimating || budget > 0, Ordering::Relaxed);
+
+    banner();
+
+    if let Err(e) = fs::create_dir_all(&root_dir) {
+        eprintln!("Error: cannot create output directory {}: {}", root_dir, e);
+        std::process::exit(1);
+    }
+    if let Err(e) = confine::set_root(&root_dir) {
+        eprintln!("Error: cannot resolve output directory {}: {}", root_dir, e);
+        std::process::exit(1);
+    }
+    if let Err(e) = sanitize::open_map(&root_dir) {
+        eprintln!("[!] Failed to read {}: {}, renamed entries will not be recorded", sanitize::NAMES_FILE, e);
+    }
+
+    if let Some(dir) = extract_dir {
+        let absolute = |p: &Path| std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf());
+        // extracted payloads must not end up in the mirror that verify and the manifest cover
+        if absolute(&dir).starts_with(absolute(Path::new(&root_dir))) {
+            eprintln!("Error: --extract must point outside the output directory");
+            std::process::exit(1);
+        }
+        println!("[*] Extracting archives into quarantine directory {}", dir.display());
+        extract::enable(dir, PathBuf::from(&root_dir));
+    }
+
+    let pool = Arc::new(ClientPool::new(&http_config, &proxies, &binds, strategy).unwrap_or_else(|e| {
+        eprintln!("Error: failed to set up HTTP client: {}", e);
+        std::process::exit(1);
+    }));
+    if !proxies.is_empty() || !binds.is_empty() {
+        println!("[*] Checking {} proxies and {} local addresses...", proxies.len(), binds.len());
+        pool.health_check(base_url, false).await;
+        pool.spawn_health_checks(base_url.to_string());
+    }
+
+    let mut start_url = format!("{}/", base_url);
+    if let Some(path) = &start_path {
+        start_url = format!("{}/{}", base_url, path);
+        match check_url(&pool, &start_url).await {
+            Ok(true) => println!("[+] URL is reachable: {} - STATUS {}", start_url, 200),
+            Ok(false) => eprintln!("[!] URL not reachable: {}", start_url),
+            Err(e) => eprintln!("Error while checking URL: {}", e),
+        }
+        println!("[+] Downloading collection: {}", path);
+    } else {
+        println!("[+] No parameter detected. Starting to download all collections...");
+    }
+
+    if !estimating && !resume && !fresh && journal::unfinished(&root_dir) {
+        eprintln!(
+            "Error: {}/{} belongs to an interrupted run; pass --resume to continue it or --fresh to start over",
+            root_dir,
+            journal::JOURNAL_FILE
+        );
+        std::process::exit(1);
+    }
+    if estimating {
+        println!("[*] Estimating sizes only, nothing will be downloaded");
+    } else {
+        println!("[*] Press Enter to Start Processing =>");
+        std::io::stdin().read_line(&mut String::new()).unwrap();
+        std::io::stdout().flush().unwrap();
+    }
+    shutdown::install();
+
+    let limit_rate = throttle::LIMIT_RATE.load(Ordering::Relaxed);
+    if limit_rate > 0 {
+        println!("[*] Download cap: {} bytes/s", limit_rate);
+        if limit_rate < MIN_SPEED.load(Ordering::Relaxed) {
+            eprintln!("[!] --limit-rate is below --min-speed; capped transfers will be reported as stalled");
+        }
+    }
+    // show concurrency limit if set
+    let concurrency_limit = CONCURRENCY.load(Ordering::Relaxed);
+    if concurrency_limit > 0 {
+        println!("[*] Concurrency limit per directory: {}", concurrency_limit);
+    }
+    if USE_ARIA.load(Ordering::Relaxed) && !estimating {
+        println!("[*] Using aria2c for downloads");
+        // First, resume any pending aria2 downloads before continuing
+        resume_pending_downloads(&root_dir, &pool).await;
+    }
+
+    // a fresh journal would discard the state of an interrupted download run
+    if !estimating || resume {
+        match Journal::open(&root_dir, resume) {
+            Ok(journal) => {
+                if resume {
+                    let (listings, completed, failed) = journal.counts();
+                    println!(
+                        "[*] Resuming from journal: {} listings, {} completed and {} failed files",
+                        listings, completed, failed
+                    );
+                }
+                let _ = JOURNAL.set(journal);
+            }
+            Err(e) => eprintln!("[!] Failed to open {}: {}, continuing without a journal", journal::JOURNAL_FILE, e),
+        }
+    }
+
+    match Manifest::open(&root_dir, manifest_format) {
+        Ok(manifest) => {
+            let _ = MANIFEST.set(manifest);
+            if let Some(manifest) = MANIFEST.get() {
+                manifest.autosave();
+            }
+            shutdown::on_exit(|| {
+                if let Some(manifest) = MANIFEST.get() {
+                    manifest.save_logged();
+                }
+                if let Some(catalog) = CATALOG.get() {
+                    catalog.flush();
+                }
+            });
+        }
+        Err(e) => eprintln!("[!] Failed to read {}: {}, hashes will not be recorded", manifest::MANIFEST_FILE, e),
+    }
+
+    if cas_layout {
+        println!("[*] Content-addressed layout: files go to {}/{}, the collection tree is a symlink view", root_dir, store::STORE_DIR);
+        let _ = store::ROOT.set(PathBuf::from(&root_dir));
+        // the view can only be rebuilt from the catalog
+        use_catalog = true;
+        if dedup::MODE.get().is_some() {
+            println!("[*] --dedup has no effect with --layout cas, content is always stored once");
+        }
+    }
+
+    if use_catalog {
+        let path = catalog_path.unwrap_or_else(|| Path::new(&root_dir).join(catalog::CATALOG_FILE));
+        match Catalog::open(&path) {
+            Ok(catalog) => {
+                println!("[*] Recording crawl in catalog {}", path.display());
+                let _ = CATALOG.set(catalog);
+            }
+            Err(e) => eprintln!("[!] Failed to open catalog {}: {}, continuing without it", path.display(), e),
+        }
+    }
+
+    // with a budget the crawl lists the failed files again and the selection decides about them
+    let previous_failures = if estimating || budget > 0 { 0 } else { retry::load_failures(&root_dir) };
+    if previous_failures > 0 {
+        println!("[*] Queued {} items from {} for retry", previous_failures, retry::FAILURES_FILE);
+    }
+
+    let mp = Arc::new(MultiProgress::new());
+    println!("Starting scrape at URL: {}", start_url);
+
+    let mut visited = HashSet::new();
+    scrape_directory(pool.clone(), base_url, &start_url, &root_dir, &mut visited, mp.clone()).await?;
+
+    let mut failed = Vec::new();
+    if budget > 0 && !shutdown::requested() {
+        // directories that could not be listed are retried first so the choice covers them
+        failed = run_deferred(pool.clone(), base_url, mp.clone()).await;
+        estimate::ENABLED.store(false, Ordering::Relaxed);
+        let plan = budget::select(estimate::files(), budget, selection);
+        // only names that end up on disk go to the name map: the chosen files and their directories
+        let mut kept = HashSet::new();
+        for file in &plan.selected {
+            kept.insert(file.file_path.as_str());
+            kept.extend(Path::new(&file.dir).ancestors().filter_map(|d| d.to_str()));
+        }
+        sanitize::record_pending(|path| kept.contains(path));
+        plan.print_report(&root_dir, budget);
+        if let Err(e) = plan.save_left_out(&root_dir) {
+            eprintln!("[!] Failed to write {}: {}", budget::LEFT_OUT_FILE, e);
+        }
+        if !shutdown::requested() {
+            download_selected(pool.clone(), plan.selected, mp.clone()).await;
+        }
+    }
+
+    let deferred = retry::pending();
+    if deferred > 0 {
+        println!("[*] Crawl finished with {} deferred items", deferred);
+    }
+    failed.extend(run_deferred(pool.clone(), base_url, mp).await);
+    if estimating {
+        if let Some(catalog) = CATALOG.get() {
+            catalog.flush();
+        }
+        estimate::print_report(&root_dir);
+        if !failed.is_empty() {
+            eprintln!("[!] {} directories could not be listed and are not counted", failed.len());
+        }
+        pool.print_stats();
+        if shutdown::requested() {
+            println!("[!] Estimate interrupted, the totals above cover only the part crawled so far.");
+            std::process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+        }
+        return Ok(());
+    }
+    if let Err(e) = retry::save_failures(&root_dir, &failed) {
+        eprintln!("[!] Failed to write {}: {}", retry::FAILURES_FILE, e);
+    }
+    STATS.add_failed(failed.len());
+    // items left over by an interrupt are resumable rather than failed
+    let file_status = if shutdown::requested() { FileStatus::Partial } else { FileStatus::Failed };
+    for item in &failed {
+        match item {
+            Deferred::Page { url, dir } => {
+                if let Some(journal) = JOURNAL.get() {
+                    journal.record_failed(url, dir);
+                }
+                if let Some(catalog) = CATALOG.get() {
+                    catalog.record_directory(url, None, dir, "failed", 0);
+                }
+            }
+            Deferred::File { url, file_path, .. } => {
+                if let Some(journal) = JOURNAL.get()
+                    && file_status == FileStatus::Failed
+                {
+                    journal.record_failed(url, file_path);
+                }
+                if let Some(catalog) = CATALOG.get() {
+                    catalog.set_status(url, file_path, file_status);
+                }
+            }
+        }
+    }
+    if !failed.is_empty() {
+        eprintln!(
+            "[!] {} items still failing, recorded in {}/{}",
+            failed.len(),
+            root_dir,
+            retry::FAILURES_FILE
+        );
+    }
+
+    if let Some(manifest) = MANIFEST.get() {
+        manifest.save_logged();
+    }
+    if let Some(catalog) = CATALOG.get() {
+        catalog.flush();
+    }
+
+    STATS.print_report();
+    pool.print_stats();
+    if shutdown::requested() {
+        println!("[!] Run interrupted. Start again with --resume to continue where it stopped.");
+        std::process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+    }
+    if let Some(journal) = JOURNAL.get() {
+        journal.finish();
+    }
+    println!("Scraping and downloading complete!");
+    Ok(())
+}
+
+/// Extract file links, or subdirectory names when there are no files, from a directory page
+fn parse_listing(html: &str) -> Listing {
+    let document = Html::parse_document(html);
+
+    // check for .pdf or .zip files
+    let link_selector =
+        Selector::parse(r#"a[href$=".pdf"], a[href$=".zip"], a[href$=".7z"]"#).unwrap();
+    let date_pattern = Regex::new(r"\d{4}-\d{2}-\d{2}(?:[ T]\d{2}:\d{2}(?::\d{2})?)?").unwrap();
+    let size_pattern = Regex::new(r"(?i)\b(\d+(?:\.\d+)?)\s*([KMGT])i?B\b").unwrap();
+    let any_link = Selector::parse("a[href]").unwrap();
+    let mut files = Vec::new();
+    let mut dates = std::collections::HashMap::new();
+    let mut sizes = std::collections::HashMap::new();
+    for link in document.select(&link_selector) {
+        let Some(href) = link.value().attr("href") else {
+            continue;
+        };
+        // the date and size, if the page shows them, sit in the same row as the link:
+        // the widest close ancestor holding no other link, so a row without them
+        // never borrows a neighbour's values
+        let row = link
+            .ancestors()
+            .take(3)
+            .filter_map(scraper::ElementRef::wrap)
+            .take_while(|row| row.select(&any_link).count() == 1)
+            .last();
+        // the link's own text is the file name, which may contain a date or a size
+        let text = row
+            .map(|row| {
+                row.descendants()
+                    .filter(|node| !node.ancestors().any(|a| a.id() == link.id()))
+                    .filter_map(|node| node.value().as_text().map(|t| &**t))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .unwrap_or_default();
+        if let Some(date) = date_pattern.find(&text) {
+            dates.insert(href.to_string(), date.as_str().to_string());
+        }
+        let size = size_pattern
+            .captures(&text)
+            .and_then(|m| throttle::parse_size(&format!("{}{}", &m[1], &m[2])));
+        if let Some(size) = size {
+            sizes.insert(href.to_string(), size);
+        }
+        files.push(href.to_string());
+    }
+    if !files.is_empty() {
+        return Listing { files, dirs: Vec::new(), dates, sizes };
+    }
+
+    let category_selector =
+        Selector::parse(r#"div.cursor-pointer span.text-white.text-xs.truncate"#).unwrap();
+    let dirs = document
+        .select(&category_selector)
+        .map(|e| e.text().collect::<Vec<_>>().join("").trim().to_string())
+        .collect();
+    Listing { dirs, ..Default::default() }
+}
+
+async fn scrape_directory(
+    pool: Arc<ClientPool>,
+    base_url: &str,
+    url: &str,
+    dir: &str,
+    visited: &mut HashSet<String>,
+    mp: Arc<MultiProgress>,
+) -> error::Result<()> {
+    reqwest::Url::parse(url).map_err(|e| Error::Parse(format!("invalid directory URL {}: {}", url, e)))?;
+    let current_path = remote_path(url);
+    if !visited.insert(current_path.clone()) {
+        println!("Already crawled {}, skipping", url);
+        return Ok(());
+    }
+    println!("Processing URL: {} | Saving to directory: {}", url, dir);
+
+    // reuse the listing from the journal when resuming, otherwise fetch the page
+    // with rate-limit and retry up to 3 times on rate-limit or network errors
+    let journaled = JOURNAL.get().and_then(|j| j.listing(url));
+    let listing = match journaled {
+        Some(listing) => {
+            println!("[*] Using journaled listing for {}", url);
+            listing
+        }
+        None => {
+            let mut attempts = 0;
+            let max_attempts = 3;
+            let response_text = loop {
+                if shutdown::requested() {
+                    visited.remove(&current_path);
+                    return Ok(());
+                }
+                attempts += 1;
+                throttle::wait_if_paused().await;
+                let rl = RATE_LIMIT_SECS.load(Ordering::Relaxed);
+                if rl > 0 {
+                    sleep(Duration::from_secs(rl)).await;
+                }
+                let route = pool.current();
+                match route.client.get(url).send().await {
+                    Ok(resp) => {
+                        let status = resp.status();
+                        if !status.is_success() {
+                            eprintln!("[!] Attempt {}/{}: HTTP status {} received from {}.", attempts, max_attempts, status, url);
+                            if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                                pool.rate_limited(&route);
+                            } else if pool::is_rate_limit_status(status) {
+                                pool.failure(&route);
+                            }
+                        } else {
+                            match resp.text().await {
+                                Ok(body) => {
+                                    pool.success(&route);
+                                    break body;
+                                }
+                                Err(e) => {
+                                    eprintln!("[!] Attempt {}/{}: Error reading body from {}: {}.", attempts, max_attempts, url, e);
+                                    pool.failure(&route);
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("[!] Attempt {}/{}: HTTP request to {} failed: {}.", attempts, max_attempts, url, e);
+                        pool.failure(&route);
+                    }
+                }
+                if attempts >= max_attempts {
+                    eprintln!("[!] Failed to fetch {} after {} attempts. Deferring for a later retry...", url, max_attempts);
+                    retry::defer(Deferred::Page { url: url.to_string(), dir: dir.to_string() });
+                    STATS.increment_deferred();
+                    if let Some(catalog) = CATALOG.get() {
+                        catalog.record_directory(url, None, dir, "deferred", 0);
+                    }
+                    visited.remove(&current_path);
+                    return Ok(());
+                }
+                // short backoff; persistent failures are deferred instead of blocking the crawl
+                sleep(Duration::from_secs(2 * attempts)).await;
+            };
+            STATS.increment_pages();
+            let listing = parse_listing(&response_text);
+            if let Some(journal) = JOURNAL.get() {
+                journal.record_listing(url, &listing);
+            }
+            listing
+        }
+    };
+
+    let remote_names: Vec<String> = listing
+        .files
+        .iter()
+        .map(|href| href.split('/').next_back().unwrap_or("").to_string())
+        .collect();
+    let links: Vec<(String, String, String)> = sanitize::local_names(&remote_names)
+        .into_iter()
+        .zip(&listing.files)
+        .filter_map(|(local_name, href)| {
+            match confine::join(dir, &local_name) {
+                Ok(file_path) => Some((local_name, href.clone(), file_path)),
+                Err(reason) => {
+                    refuse_name("file", &local_name, url, &reason);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    for (name, href, file_path) in &links {
+        let remote_name = href.split('/').next_back().unwrap_or(name);
+        sanitize::record(&absolute_url(base_url, href), remote_name, file_path);
+        if let Some(catalog) = CATALOG.get() {
+            catalog.record_file(&absolute_url(base_url, href), url, remote_name, file_path);
+        }
+    }
+    if let Some(catalog) = CATALOG.get() {
+        catalog.record_directory(url, None, dir, "listed", links.len());
+    }
+    for (href, date) in &listing.dates {
+        metadata::remember_listing_date(&absolute_url(base_url, href), date);
+    }
+
+    if !links.is_empty() {
+        println!(
+            "Found {} files at {}: {:?}",
+            links.len(),
+            url,
+            links.iter().map(|(name, _, _)| name).collect::<Vec<_>>()
+        );
+        // bounded concurrency per directory via stream buffer_unordered
+        let concurrency = CONCURRENCY.load(Ordering::Relaxed);
+        let max_concurrency = if concurrency == 0 { links.len() } else { concurrency };
+        if estimate::ENABLED.load(Ordering::Relaxed) {
+            futures::stream::iter(links.iter().map(|(name, href, file_path)| {
+                let listed = listing.sizes.get(href).copied();
+                let pool = &pool;
+                async move { estimate::measure(pool, name, &absolute_url(base_url, href), file_path, dir, listed).await }
+            }))
+            .buffer_unordered(max_concurrency)
+            .for_each(|file| async { estimate::record(file) })
+            .await;
+            return Ok(());
+        }
+        futures::stream::iter(links.into_iter().map(|(name, href, file_path)| {
+            download_link(Arc::clone(&pool), name, absolute_url(base_url, &href), dir.to_string(), file_path, mp.clone())
+        }))
+        .buffer_unordered(max_concurrency)
+        .for_each(|_| async {})
+        .await;
+    } else {
+
+        let mut categories = listing.dirs;
+        categories.retain(|category| {
+            let path = remote_path(&format!("{}/{}", url.trim_end_matches('/'), category));
+            if visited.contains(&path) {
+                return false;
+            }
+            // listings can link back up the tree; same-named children such as `2023/2023` are real folders
+            if is_ancestor(&path, &current_path) {
+                println!("[*] Not descending into /{}: links back up the tree", path);
+                return false;
+            }
+            if path.split('/').filter(|s| !s.is_empty()).count() > MAX_DEPTH {
+                println!("[*] Not descending into /{}: deeper than {} levels", path, MAX_DEPTH);
+                return false;
+            }
+            true
+        });
+
+        if categories.is_empty() {
+            println!("No subdirectories found at {}", url);
+            return Ok(());
+        }
+
+        println!("Found subdirectories at {}: {:?}", url, categories);
+        let local_names = sanitize::local_names(&categories);
+        for (category, local_name) in categories.into_iter().zip(local_names) {
+            if shutdown::requested() {
+                break;
+            }
+            let category_dir = match confine::join(dir, &local_name) {
+                Ok(path) => path,
+                Err(reason) => {
+                    refuse_name("directory", &category, url, &reason);
+                    continue;
+                }
+            };
+            let category_url = format!("{}/{}", url.trim_end_matches('/'), category);
+            sanitize::record(&category_url, &category, &category_dir);
+            if estimate::ENABLED.load(Ordering::Relaxed) {
+                // nothing is written when only estimating
+            } else if let Err(e) = fs::create_dir_all(&category_dir) {
+                eprintln!("[!] {}, deferring {} for a later retry.", Error::io(&category_dir, e), category_url);
+                retry::defer(Deferred::Page { url: category_url, dir: category_dir });
+                STATS.increment_deferred();
+                continue;
+            }
+            if let Some(catalog) = CATALOG.get() {
+                catalog.record_directory(&category_url, Some(url), &category_dir, "discovered", 0);
+            }
+
+            let recursive_call = Box::pin(scrape_directory(
+                Arc::clone(&pool),
+                base_url,
+                &category_url,
+                &category_dir,
+                visited,
+                mp.clone(),
+            ));
+
+            if let Err(e) = recursive_call.await {
+                eprintln!("Failed to scrape {}: {}", category_url, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Skip a file that is already complete on disk, doing the bookkeeping a
+/// download would. A file aria2c is still working on is not complete, unless
+/// the journal says it finished.
+fn skip_existing(name: &str, file_url: &str, file_path: &str) -> bool {
+    let journal = JOURNAL.get();
+    if !Path::new(file_path).exists() {
+        if journal.is_some_and(|j| j.failed(file_url)) {
+            println!("Retrying {}: failed in the previous run", name);
+        }
+        return false;
+    }
+    if journal.is_some_and(|j| j.completed(file_url)) {
+        println!("Skipping {}: completed in the journal", name);
+    } else if USE_ARIA.load(Ordering::Relaxed) && Path::new(&format!("{}.aria2", file_path)).exists() {
+        println!("Resuming incomplete download for {} (found .aria2)", name);
+        return false;
+    } else {
+        println!("Skipping {}: already exists at {}", name, file_path);
+    }
+    STATS.increment_skipped();
+    harden::apply(file_path);
+    if let Some(catalog) = CATALOG.get() {
+        let size = fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+        catalog.set_complete(file_url, file_path, size, false);
+    }
+    true
+}
+
+/// Download one listed file unless it is already there, deferring it on failure
+async fn download_link(
+    pool: Arc<ClientPool>,
+    name: String,
+    file_url: String,
+    dir: String,
+    file_path: String,
+    mp: Arc<MultiProgress>,
+) {
+    if shutdown::requested() || skip_existing(&name, &file_url, &file_path) {
+        return;
+    }
+    println!("Downloading {} to {}", name, file_path);
+    if let Err(e) = fetch_file(&pool, &name, &file_url, &dir, &file_path, mp).await {
+        if shutdown::requested() {
+            eprintln!("[!] Download of {} interrupted, recording it for the next run.", name);
+        } else {
+            eprintln!("[!] Failed to download {}: {}, deferring for a later retry.", name, e);
+        }
+        if let Some(catalog) = CATALOG.get() {
+            catalog.set_status(&file_url, &file_path, FileStatus::Deferred);
+        }
+        retry::defer(Deferred::File { name, url: file_url, file_path, dir });
+        STATS.increment_deferred();
+    }
+}
+
+/// Download the files chosen by --budget, a directory at a time like the crawl
+async fn download_selected(pool: Arc<ClientPool>, files: Vec<estimate::RemoteFile>, mp: Arc<MultiProgress>) {
+    let mut by_dir: std::collections::BTreeMap<String, Vec<estimate::RemoteFile>> = std::collections::BTreeMap::new();
+    for file in files {
+        by_dir.entry(file.dir.clone()).or_default().push(file);
+    }
+    for (dir, files) in by_dir {
+        if shutdown::requested() {
+            break;
+        }
+        let concurrency = CONCURRENCY.load(Ordering::Relaxed);
+        let max_concurrency = if concurrency == 0 { files.len() } else { concurrency };
+        futures::stream::iter(files.into_iter().map(|file| {
+            download_link(Arc::clone(&pool), file.name, file.url, dir.clone(), file.file_path, mp.clone())
+        }))
+        .buffer_unordered(max_concurrency)
+        .for_each(|_| async {})
+        .await;
+    }
+}
+
+/// Make a single download attempt, using aria2c or the built-in downloader
+async fn fetch_file(
+    pool: &ClientPool,
+    name: &str,
+    file_url: &str,
+    dir: &str,
+    file_path: &str,
+    mp: Arc<MultiProgress>,
+) -> error::Result<()> {
+    reqwest::Url::parse(file_url).map_err(|e| Error::Parse(format!("invalid file URL {}: {}", file_url, e)))?;
+    throttle::wait_if_paused().await;
+    space::wait_for_room(dir, 0).await?;
+    let route = pool.current();
+    // Determine per-file if aria2c should be used based on threshold
+    let mut use_aria_for_file = USE_ARIA.load(Ordering::Relaxed);
+    if use_aria_for_file && !route.supports_aria() {
+        // aria2c only understands HTTP proxies
+        use_aria_for_file = false;
+    }
+    let threshold = ARIA_THRESHOLD.load(Ordering::Relaxed);
+    if use_aria_for_file && threshold > 0 {
+        match route.client.head(file_url).send().await {
+            Ok(resp) => {
+                let len = resp
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|h| h.to_str().ok())
+                    .and_then(|s| s.parse::<u64>().ok());
+                match len {
+                    // aria2c preallocates, so the whole file has to fit before it starts
+                    Some(len) if len > threshold => space::wait_for_room(dir, len).await?,
+                    _ => use_aria_for_file = false,
+                }
+            }
+            Err(e) => {
+                eprintln!("[!] Failed to HEAD {}: {}, falling back to HTTP download", file_url, e);
+                use_aria_for_file = false;
+            }
+        }
+    }
+    if use_aria_for_file {
+        let command = || {
+            let mut cmd = Command::new("aria2c");
+            cmd.arg("-c")
+                .arg("--auto-file-renaming=false")
+                .arg("-d").arg(dir)
+                .arg("-o").arg(name)
+                .args(pool.aria_args());
+            if let Some(proxy) = route.aria_proxy() {
+                cmd.arg(format!("--all-proxy={}", proxy));
+            }
+            if let Some(bind) = route.bind {
+                cmd.arg(format!("--interface={}", bind));
+            }
+            if MIN_SPEED.load(Ordering::Relaxed) > 0 && STALL_WINDOW_SECS.load(Ordering::Relaxed) > 0 {
+                cmd.arg(format!("--lowest-speed-limit={}", MIN_SPEED.load(Ordering::Relaxed)));
+            }
+            // Apply aria2c options: user-provided or default for higher concurrency
+            let aria_opts = std::env::var("ARIA_OPTS").unwrap_or_else(|_| "-x 4 -s 4".to_string());
+            for token in aria_opts.split_whitespace() {
+                cmd.arg(token);
+            }
+            cmd.arg(file_url);
+            cmd
+        };
+        let status = run_aria(name, command).await;
+        match status {
+            Ok(None) => {
+                eprintln!("[!] aria2c stopped for {}, the .aria2 control file allows resuming", name);
+                Err(Error::Interrupted)
+            }
+            Ok(Some(s)) if s.success() => {
+                match validate::check_file(name, Path::new(file_path)) {
+                    Ok(Ok(())) => {}
+                    Ok(Err(reason)) => {
+                        let kept = validate::set_aside(Path::new(file_path), file_path)
+                            .unwrap_or_else(|e| format!("nowhere ({})", e));
+                        let e = Error::Validation(format!("{}, body kept in {}", reason, kept));
+                        STATS.increment_rejected();
+                        pool::report(pool, &route, &e);
+                        return Err(e);
+                    }
+                    Err(e) => eprintln!("[!] Could not check the contents of {}: {}", file_path, e),
+                }
+                println!("Saved {} to {}", name, file_path);
+                pool.success(&route);
+                STATS.increment_files();
+                let size = fs::metadata(file_path).map(|m| m.len()).unwrap_or(0);
+                STATS.add_bytes(size);
+                if let Some(journal) = JOURNAL.get() {
+                    journal.record_completed(file_url, file_path);
+                }
+                if let Some(catalog) = CATALOG.get() {
+                    catalog.set_complete(file_url, file_path, size, true);
+                }
+                // aria2c writes the file itself, so it is hashed once complete and
+                // a HEAD request supplies the headers for its timestamp and sidecar
+                let headers = match route.client.head(file_url).send().await {
+                    Ok(resp) => resp.headers().clone(),
+                    Err(_) => reqwest::header::HeaderMap::new(),
+                };
+                let path = PathBuf::from(file_path);
+                match tokio::task::spawn_blocking(move || hash::hash_file(&path)).await {
+                    Ok(Ok((size, hashes))) => {
+                        metadata::apply(file_path, file_url, &headers, metadata::Downloader::Aria2, size, &hashes);
+                        record_hashes(file_url, file_path, size, &hashes);
+                        inspect_archive(name, file_path).await;
+                        extract_archive(name, file_path).await;
+                        harden::apply(file_path);
+                    }
+                    Ok(Err(e)) => eprintln!("[!] Failed to hash {}", Error::io(file_path, e)),
+                    Err(e) => eprintln!("[!] Failed to hash {}: {}", file_path, e),
+                }
+                Ok(())
+            }
+            // aria2c exit code 5: aborted because the speed stayed below --lowest-speed-limit
+            Ok(Some(s)) if s.code() == Some(5) => {
+                STATS.increment_stalls();
+                Err(Error::Aria2("transfer stalled below --min-speed".to_string()))
+            }
+            // aria2c exit code 9: not enough disk space
+            Ok(Some(s)) if s.code() == Some(9) => {
+                space::wait_for_room(dir, 0).await?;
+                Err(Error::Aria2("ran out of disk space".to_string()))
+            }
+            Ok(Some(s)) => {
+                pool.failure(&route);
+                Err(Error::Aria2(format!("exited with {}", s)))
+            }
+            Err(e) => Err(Error::Aria2(format!("failed to start: {}", e))),
+        }
+    } else {
+        let mut stalls = 0;
+        loop {
+            match download_file(&route.client, file_url, file_path, mp.clone()).await {
+                Ok(Downloaded { bytes, hashes, headers }) => {
+                    println!("Saved {} to {}", name, file_path);
+                    if let Some(journal) = JOURNAL.get() {
+                        journal.record_completed(file_url, file_path);
+                    }
+                    let size = fs::metadata(file_path).map(|m| m.len()).unwrap_or(bytes);
+                    if let Some(catalog) = CATALOG.get() {
+                        catalog.set_complete(file_url, file_path, size, true);
+                    }
+                    metadata::apply(file_path, file_url, &headers, metadata::Downloader::BuiltIn, size, &hashes);
+                    record_hashes(file_url, file_path, size, &hashes);
+                    inspect_archive(name, file_path).await;
+                    extract_archive(name, file_path).await;
+                    harden::apply(file_path);
+                    pool.success(&route);
+                    STATS.increment_files();
+                    STATS.add_bytes(bytes);
+                    return Ok(());
+                }
+                Err(e @ Error::Stalled { .. }) => {
+                    STATS.increment_stalls();
+                    stalls += 1;
+                    if stalls > STALL_RETRIES {
+                        eprintln!("[!] Download of {} keeps stalling", name);
+                        return Err(e);
+                    }
+                    eprintln!("[!] {} for {}, resuming from partial data ({}/{})", e, name, stalls, STALL_RETRIES);
+                }
+                Err(Error::DiskFull { needed, .. }) => {
+                    // the .part file is kept, the retry resumes from it
+                    space::wait_for_room(dir, needed).await?;
+                }
+                Err(Error::Interrupted) => {
+                    eprintln!("[!] Download of {} interrupted, partial data kept in {}.part", name, file_path);
+                    return Err(Error::Interrupted);
+                }
+                Err(e) => {
+                    eprintln!("[!] Download of {} via {} failed: {}", name, route.label, e);
+                    if let Error::Validation(_) = e {
+                        STATS.increment_rejected();
+                    }
+                    pool::report(pool, &route, &e);
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+/// Store the digests of a completed file in the manifest and the catalog,
+/// replacing it with a link when --dedup is set and the content is already present
+fn record_hashes(file_url: &str, file_path: &str, size: u64, hashes: &Hashes) {
+    let mut original = MANIFEST.get().and_then(|m| m.record(file_url, file_path, size, hashes));
+    let mut kind = "file";
+    if let Some(root) = store::ROOT.get() {
+        match store::ingest(root, Path::new(file_path), size, &hashes.sha256) {
+            Ok((object, existed)) => {
+                if existed {
+                    println!("[+] {} is already in the store ({} bytes saved)", file_path, size);
+                    STATS.add_dedup(size);
+                }
+                kind = "cas";
+                original = Some(object);
+            }
+            Err(e) => {
+                eprintln!("[!] Failed to move {} into the store: {}", file_path, e);
+                original = None;
+            }
+        }
+    } else if let (Some(original), Some(&mode)) = (&original, dedup::MODE.get()) {
+        match dedup::replace(mode, original, Path::new(file_path)) {
+            Ok(saved) => {
+                println!("[+] {} duplicates {}, replaced by a {} ({} bytes saved)", file_path, original.display(), mode.as_str(), saved);
+                STATS.add_dedup(saved);
+                kind = mode.as_str();
+            }
+            Err(e) => eprintln!("[!] Failed to {} {} to {}: {}", mode.as_str(), file_path, original.display(), e),
+        }
+    }
+    if let Some(catalog) = CATALOG.get() {
+        catalog.set_hashes(file_url, hashes);
+        let linked_to = if kind == "file" { None } else { original.as_deref().and_then(Path::to_str) };
+        catalog.record_location(&hashes.sha256, file_path, file_url, kind, linked_to);
+    }
+}
+
+/// List the contents of a downloaded archive into the manifest when --inspect is set
+async fn inspect_archive(name: &str, file_path: &str) {
+    let Some(format) = archive::Format::from_name(name) else {
+        return;
+    };
+    if !archive::INSPECT.load(Ordering::Relaxed) {
+        return;
+    }
+    let path = PathBuf::from(file_path);
+    let listing = match tokio::task::spawn_blocking(move || archive::inspect(&path, format)).await {
+        Ok(listing) => listing,
+        Err(e) => {
+            eprintln!("[!] Failed to inspect {}: {}", file_path, e);
+            return;
+        }
+    };
+    match (&listing.error, &listing.password) {
+        (Some(e), _) => eprintln!("[!] {}: {} entries listed, {}", name, listing.entries.len(), e),
+        (None, Some(pw)) => println!("[*] {}: {} entries (password \"{}\")", name, listing.entries.len(), pw),
+        (None, None) => println!("[*] {}: {} entries", name, listing.entries.len()),
+    }
+    if let Some(manifest) = MANIFEST.get() {
+        manifest.set_archive(file_path, listing);
+    }
+}
+
+/// Unpack a downloaded archive into the quarantine directory when --extract is set
+async fn extract_archive(name: &str, file_path: &str) {
+    let (name_owned, path) = (name.to_string(), PathBuf::from(file_path));
+    let extracted = tokio::task::spawn_blocking(move || {
+        let result = extract::extract_download(&name_owned, &path);
+        if let Some(Ok(summary)) = &result
+            && harden::SAMPLE_SAFE.load(Ordering::Relaxed)
+        {
+            harden::harden_tree(&summary.dest, false, &mut harden::Report::default());
+        }
+        result
+    });
+    let result = match extracted.await {
+        Ok(Some(result)) => result,
+        Ok(None) => return,
+        Err(e) => Err(e.to_string()),
+    };
+    match result {
+        Ok(summary) => {
+            println!(
+                "[+] Extracted {} files ({} bytes) from {} into {}",
+                summary.files,
+                summary.bytes,
+                name,
+                summary.dest.display()
+            );
+            if summary.renamed > 0 {
+                println!("[*] Renamed {} executables to *{}", summary.renamed, extract::MALWARE_SUFFIX);
+            }
+            for (entry, reason) in &summary.skipped {
+                eprintln!("[!] Skipped entry {} in {}: {}", entry, name, reason);
+            }
+        }
+        Err(e) => eprintln!("[!] Not extracting {}: {}", name, e),
+    }
+}
+
+/// Report a remote name that would not stay inside the output directory
+fn refuse_name(kind: &str, name: &str, url: &str, reason: &str) {
+    eprintln!("[!] Refusing {} name {:?} listed at {}: {}", kind, name, url, reason);
+    STATS.increment_unsafe_names();
+}
+
+/// Retry deferred pages and files in cool-down separated rounds.
+/// Returns the items that still failed after the last round.
+async fn run_deferred(
+    pool: Arc<ClientPool>,
+    base_url: &str,
+    mp: Arc<MultiProgress>,
+) -> Vec<Deferred> {
+    let rounds = retry::RETRY_ROUNDS.load(Ordering::Relaxed);
+    for round in 1..=rounds {
+        if shutdown::requested() {
+            break;
+        }
+        let items = retry::take_all();
+        if items.is_empty() {
+            return Vec::new();
+        }
+        retry::cooldown(round, rounds, items.len()).await;
+        if shutdown::requested() {
+            for item in items {
+                retry::defer(item);
+            }
+            break;
+        }
+
+        let mut files = Vec::new();
+        for item in items {
+            match item {
+                Deferred::Page { url, dir } => {
+                    // the retry list is a file on disk; do not trust it more than a listing
+                    if let Err(reason) = confine::check(&dir) {
+                        refuse_name("directory", &dir, &url, &reason);
+                        continue;
+                    }
+                    let mut visited = HashSet::new();
+                    if let Err(e) = Box::pin(scrape_directory(
+                        Arc::clone(&pool),
+                        base_url,
+                        &url,
+                        &dir,
+                        &mut visited,
+                        mp.clone(),
+                    ))
+                    .await
+                    {
+                        eprintln!("Failed to scrape {}: {}", url, e);
+                    }
+                }
+                Deferred::File { ref file_path, ref url, .. } if let Err(reason) = confine::check(file_path) => {
+                    refuse_name("file", file_path, url, &reason);
+                }
+                file @ Deferred::File { .. } => files.push(file),
+            }
+        }
+
+        let concurrency = CONCURRENCY.load(Ordering::Relaxed);
+        let max_concurrency = if concurrency == 0 { files.len().max(1) } else { concurrency };
+        futures::stream::iter(files.into_iter().map(|item| {
+            let pool = Arc::clone(&pool);
+            let mp = mp.clone();
+            async move {
+                if shutdown::requested() {
+                    retry::defer(item);
+                    return;
+                }
+                if let Deferred::File { name, url, file_path, dir } = &item {
+                    // the crawl, or an earlier round, may have completed it since it was queued
+                    if skip_existing(name, url, file_path) {
+                        return;
+                    }
+                    println!("Retrying {} to {}", name, file_path);
+                    if let Err(e) = fetch_file(&pool, name, url, dir, file_path, mp).await {
+                        eprintln!("[!] Retry of {} failed: {}", name, e);
+                        retry::defer(item);
+                    }
+                }
+            }
+        }))
+        .buffer_unordered(max_concurrency)
+        .for_each(|_| async {})
+        .await;
+    }
+    retry::take_all()
+}
+
+/// Result of a completed built-in download
+struct Downloaded {
+    /// Bytes transferred by this attempt
+    bytes: u64,
+    /// Digests of the whole file
+    hashes: Hashes,
+    /// Response headers, for the timestamp and the metadata sidecar
+    headers: reqwest::header::HeaderMap,
+}
+
+/// Download `url` to `file_path` through a `.part` file, resuming a previous
+/// partial download with a range request when the server supports it.
+async fn download_file(client: &Client, url: &str, file_path: &str, mp: Arc<MultiProgress>) -> error::Result<Downloaded> {
+    let rl = RATE_LIMIT_SECS.load(Ordering::Relaxed);
+    if rl > 0 {
+        sleep(Duration::from_secs(rl)).await;
+    }
+    let part_path = format!("{}.part", file_path);
+    let mut offset = fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+    // request raw stream without content decoding to avoid loading large bodies into memory
+    let mut resp = loop {
+        let mut req = client.get(url).header(ACCEPT_ENCODING, "identity");
+        if offset > 0 {
+            req = req.header(RANGE, format!("bytes={}-", offset));
+        }
+        let resp = req.send().await?;
+        if offset > 0 && resp.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            eprintln!("[!] Server rejected resuming {}, starting over", file_path);
+            fs::remove_file(&part_path).at(&part_path)?;
+            offset = 0;
+            continue;
+        }
+        break resp.error_for_status()?;
+    };
+    let resumed = offset > 0 && resp.status() == StatusCode::PARTIAL_CONTENT;
+    if offset > 0 && !resumed {
+        println!("[*] Server ignored the range request for {}, starting over", file_path);
+        offset = 0;
+    }
+    let name = Path::new(file_path).file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    if let Err(reason) = validate::check_content_type(name, content_type) {
+        // keep the start of the error page for diagnosis, not at the real path
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= validate::REJECTED_MAX_BYTES {
+                break;
+            }
+        }
+        let rejected = format!("{}{}", file_path, validate::REJECTED_SUFFIX);
+        if let Some(parent) = Path::new(file_path).parent() {
+            fs::create_dir_all(parent).at(parent)?;
+        }
+        fs::write(&rejected, &body).at(&rejected)?;
+        return Err(Error::Validation(format!("{}, body kept in {}", reason, rejected)));
+    }
+    let dir = Path::new(file_path).parent().and_then(Path::to_str).unwrap_or(".");
+    let expected = resp.content_length();
+    space::check(dir, expected.unwrap_or(0))?;
+    let total_size = expected.unwrap_or(0) + offset;
+    let show_progress = total_size > 50 * 1024 * 1024;
+
+    if let Some(parent) = Path::new(file_path).parent() {
+        fs::create_dir_all(parent).at(parent)?;
+    }
+    // chunks are hashed as they are written; a resumed download first hashes the data it already has
+    let mut hasher = Hasher::new();
+    let mut file = if resumed {
+        println!("[*] Resuming {} from byte {}", file_path, offset);
+        File::open(&part_path).and_then(|f| hasher.update_reader(f)).at(&part_path)?;
+        fs::OpenOptions::new().append(true).open(&part_path).at(&part_path)?
+    } else {
+        File::create(&part_path).at(&part_path)?
+    };
+    let pb = if show_progress {
+        let pb = mp.add(ProgressBar::new(total_size));
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template("{prefix} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({percent:.2}%)")
+                .unwrap()
+                .progress_chars("=>-")
+        );
+        pb.set_prefix(file_path.to_string());
+        pb.set_position(offset);
+        Some(pb)
+    } else {
+        None
+    };
+
+    // abort when fewer than min_speed * window bytes arrive within a stall window
+    let min_speed = MIN_SPEED.load(Ordering::Relaxed);
+    let window_secs = STALL_WINDOW_SECS.load(Ordering::Relaxed);
+    let stall_check = min_speed > 0 && window_secs > 0;
+    let window = Duration::from_secs(window_secs);
+    let mut window_start = Instant::now();
+    let mut window_bytes = 0u64;
+    let mut received = 0u64;
+    let mut next_space_check = space::CHECK_EVERY_BYTES;
+    loop {
+        let deadline = if stall_check { window_start + window } else { Instant::now() + Duration::from_secs(3600) };
+        let next = tokio::select! {
+            chunk = tokio::time::timeout_at(deadline, resp.chunk()) => match chunk {
+                Ok(chunk) => Some(chunk?),
+                Err(_) => None,
+            },
+            _ = shutdown::wait() => {
+                // everything written so far stays in the .part file for resuming
+                file.flush().at(&part_path)?;
+                if let Some(pb) = &pb {
+                    pb.abandon();
+                }
+                return Err(Error::Interrupted);
+            }
+        };
+        match next {
+            Some(Some(chunk)) => {
+                if let Err(e) = file.write_all(&chunk) {
+                    if let Some(pb) = &pb {
+                        pb.abandon();
+                    }
+                    return Err(Error::io(&part_path, e));
+                }
+                hasher.update(&chunk);
+                window_bytes += chunk.len() as u64;
+                received += chunk.len() as u64;
+                if let Some(pb) = &pb {
+                    pb.inc(chunk.len() as u64);
+                }
+                if received >= next_space_check {
+                    next_space_check = received + space::CHECK_EVERY_BYTES;
+                    if let Err(e) = space::check(dir, expected.unwrap_or(0).saturating_sub(received)) {
+                        file.flush().at(&part_path)?;
+                        if let Some(pb) = &pb {
+                            pb.abandon();
+                        }
+                        return Err(e);
+                    }
+                }
+                // time spent throttled or paused does not count against the stall window
+                window_start += throttle::acquire(chunk.len()).await;
+            }
+            Some(None) => break,
+            // no data for the rest of the window
+            None => {}
+        }
+        if stall_check && window_start.elapsed() >= window {
+            if window_bytes < min_speed * window_secs {
+                if let Some(pb) = &pb {
+                    pb.abandon();
+                }
+                return Err(Error::Stalled { bytes: window_bytes, secs: window_secs });
+            }
+            window_start = Instant::now();
+            window_bytes = 0;
+        }
+    }
+    file.flush().at(&part_path)?;
+    drop(file);
+    if let Err(reason) = validate::check_file(name, Path::new(&part_path)).at(&part_path)? {
+        if let Some(pb) = &pb {
+            pb.abandon();
+        }
+        let rejected = validate::set_aside(Path::new(&part_path), file_path).at(&part_path)?;
+        return Err(Error::Validation(format!("{}, body kept in {}", reason, rejected)));
+    }
+    fs::rename(&part_path, file_path).at(file_path)?;
+    if let Some(pb) = pb {
+        pb.finish_with_message("done");
+    }
+    Ok(Downloaded { bytes: received, hashes: hasher.finalize(), headers: resp.headers().clone() })
+}
+
+/// Remote directory path of a URL, percent-encoded the same way however the
+/// URL was written, with `.` and `..` resolved and no empty segments
+fn remote_path(url: &str) -> String {
+    let path = reqwest::Url::parse(url).map(|u| u.path().to_string()).unwrap_or_else(|_| url.to_string());
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            other => segments.push(other),
+        }
+    }
+    segments.join("/")
+}
+
+/// Whether remote path `path` is `current` or one of the directories above it
+fn is_ancestor(path: &str, current: &str) -> bool {
+    path.is_empty() || current == path || current.starts_with(&format!("{}/", path))
+}
+
+/// Resolve a listing href against the site root
+fn absolute_url(base_url: &str, href: &str) -> String {
+    if href.starts_with("http") {
+        href.to_string()
+    } else {
+        format!("{}{}", base_url, href)
+    }
+}
+
+async fn check_url(pool: &ClientPool, url: &str) -> error::Result<bool> {
+    let response = pool.current().client.get(url).send().await?;
+    Ok(response.status().is_success())
+}
+
+fn banner() {
+    println!("
+██╗   ██╗██╗  ██╗      ██╗   ██╗ ██████╗     ███████╗ ██████╗██████╗  █████╗ ██████╗ ███████╗██████╗ 
+██║   ██║╚██╗██╔╝      ██║   ██║██╔════╝     ██╔════╝██╔════╝██╔══██╗██╔══██╗██╔══██╗██╔════╝██╔══██╗
+██║   ██║ ╚███╔╝ █████╗██║   ██║██║  ███╗    ███████╗██║     ██████╔╝███████║██████╔╝█████╗  ██████╔╝
+╚██╗ ██╔╝ ██╔██╗ ╚════╝██║   ██║██║   ██║    ╚════██║██║     ██╔══██╗██╔══██║██╔═══╝ ██╔══╝  ██╔══██╗
+ ╚████╔╝ ██╔╝ ██╗      ╚██████╔╝╚██████╔╝    ███████║╚██████╗██║  ██║██║  ██║██║     ███████╗██║  ██║
   ╚═══╝  ╚═╝  ╚═╝       ╚═════╝  ╚═════╝     ╚══════╝ ╚═════╝╚═╝  ╚═╝╚═╝  ╚═╝╚═╝     ╚══════╝╚═╝  ╚═╝
 
     An Fast Scraper that scraps vx-underground.org Collections....
                                                         Ft. @5mukx                                                                                
     ")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_dates_and_sizes_come_from_the_links_own_row() {
+        let html = r#"<table>
+            <tr><td><a href="/a.zip">a.zip</a></td><td>2023-04-01</td><td>1.5 MB</td></tr>
+            <tr><td><a href="/b.zip">b.zip</a></td><td></td><td></td></tr>
+            <tr><td><a href="/2021-01-02 report 3 MB.pdf">2021-01-02 report 3 MB.pdf</a></td></tr>
+        </table>"#;
+        let listing = parse_listing(html);
+        assert_eq!(listing.files.len(), 3);
+        assert_eq!(listing.dates.get("/a.zip").map(String::as_str), Some("2023-04-01"));
+        assert_eq!(listing.sizes.get("/a.zip"), Some(&(3 << 19)));
+        // a row without values records nothing rather than a neighbour's
+        assert!(!listing.dates.contains_key("/b.zip"));
+        assert!(!listing.sizes.contains_key("/b.zip"));
+        // a date or size in the file name is not the file's
+        assert!(!listing.dates.contains_key("/2021-01-02 report 3 MB.pdf"));
+        assert!(!listing.sizes.contains_key("/2021-01-02 report 3 MB.pdf"));
+    }
+
+    #[test]
+    fn listing_without_rows_records_no_dates() {
+        let html = r#"<div><a href="/a.pdf">a.pdf</a> <a href="/b.pdf">b.pdf</a> 2023-04-01 10 KB</div>"#;
+        let listing = parse_listing(html);
+        assert_eq!(listing.files.len(), 2);
+        assert!(listing.dates.is_empty());
+        assert!(listing.sizes.is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+/*
+    Hash manifest of the local mirror.
+    Completed downloads are recorded with their URL, size and digests in
+    `manifest.json` at the output root, keyed by path relative to the root,
+    and optionally as `sha256sum`-compatible `SHA256SUMS` files per directory.
+*/
+
+use crate::archive::ArchiveListing;
+use crate::hash::Hashes;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Name of the JSON manifest inside the output directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Name of the per-directory checksum files
+pub const SUMS_FILE: &str = "SHA256SUMS";
+/// Completed entries between intermediate saves
+const SAVE_EVERY: usize = 100;
+/// Seconds between saves of entries recorded since the last one
+const SAVE_SECS: u64 = 30;
+
+/// Which manifest files to write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Sha256Sums,
+    Both,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "json" => Some(Format::Json),
+            "sha256sums" | "sums" => Some(Format::Sha256Sums),
+            "both" => Some(Format::Both),
+            _ => None,
+        }
+    }
+
+    fn json(self) -> bool {
+        self != Format::Sha256Sums
+    }
+
+    fn sums(self) -> bool {
+        self != Format::Json
+    }
+}
+
+/// Manifest record of one file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub url: String,
+    pub size: u64,
+    #[serde(flatten)]
+    pub hashes: Hashes,
+    pub recorded_at: String,
+    /// Contents of the file when it is an inspected archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<ArchiveListing>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct ManifestData {
+    /// Files keyed by path relative to the output directory, always with `/` separators
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+struct State {
+    data: ManifestData,
+    /// Directories whose SHA256SUMS must be rewritten
+    dirty: BTreeSet<String>,
+    unsaved: usize,
+    /// First key recorded for each SHA-256
+    by_sha256: HashMap<String, String>,
+}
+
+pub struct Manifest {
+    root: PathBuf,
+    format: Format,
+    state: Mutex<State>,
+}
+
+/// Path of `path` relative to `root`, with `/` separators
+pub fn relative_key(root: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Read `manifest.json` from `root_dir`; an absent manifest is empty
+pub fn load(root_dir: &Path) -> io::Result<ManifestData> {
+    match fs::read_to_string(root_dir.join(MANIFEST_FILE)) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ManifestData::default()),
+        Err(e) => Err(e),
+    }
+}
+
+impl Manifest {
+    /// Load the existing manifest in `root_dir` so this run adds to it
+    pub fn open(root_dir: &str, format: Format) -> io::Result<Self> {
+        let root = PathBuf::from(root_dir);
+        let data = load(&root)?;
+        let mut by_sha256 = HashMap::new();
+        for (key, entry) in &data.files {
+            by_sha256.entry(entry.hashes.sha256.clone()).or_insert_with(|| key.clone());
+        }
+        let state = State { data, dirty: BTreeSet::new(), unsaved: 0, by_sha256 };
+        Ok(Self { root, format, state: Mutex::new(state) })
+    }
+
+    /// Record the digests of a completed file. Returns the path of an earlier
+    /// file in the mirror with the same content, if there is one.
+    pub fn record(&self, url: &str, file_path: &str, size: u64, hashes: &Hashes) -> Option<PathBuf> {
+        let key = relative_key(&self.root, Path::new(file_path));
+        let dir = key.rsplit_once('/').map(|(d, _)| d.to_string()).unwrap_or_default();
+        let entry = FileEntry {
+            url: url.to_string(),
+            size,
+            hashes: hashes.clone(),
+            recorded_at: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            archive: None,
+        };
+        let (original, save_now) = {
+            let mut state = self.state.lock().unwrap();
+            let original = match state.by_sha256.get(&hashes.sha256) {
+                // the earlier file must still exist and still hold this content
+                Some(other) if *other != key
+                    && state.data.files.get(other).is_some_and(|e| e.hashes.sha256 == hashes.sha256)
+                    && self.root.join(other).is_file() =>
+                {
+                    Some(self.root.join(other))
+                }
+                _ => None,
+            };
+            if original.is_none() {
+                state.by_sha256.insert(hashes.sha256.clone(), key.clone());
+            }
+            state.data.files.insert(key, entry);
+            state.dirty.insert(dir);
+            state.unsaved += 1;
+            (original, state.unsaved >= SAVE_EVERY)
+        };
+        if save_now {
+            self.save_logged();
+        }
+        original
+    }
+
+    /// Attach an archive listing to the entry of a recorded file
+    pub fn set_archive(&self, file_path: &str, listing: ArchiveListing) {
+        let key = relative_key(&self.root, Path::new(file_path));
+        let mut state = self.state.lock().unwrap();
+        if let Some(entry) = state.data.files.get_mut(&key) {
+            entry.archive = Some(listing);
+            state.unsaved += 1;
+        }
+    }
+
+    /// Move the entry of a renamed file to its new path
+    pub fn rename(&self, old_path: &Path, new_path: &Path) {
+        let (old, new) = (relative_key(&self.root, old_path), relative_key(&self.root, new_path));
+        let mut state = self.state.lock().unwrap();
+        if let Some(entry) = state.data.files.remove(&old) {
+            if state.by_sha256.get(&entry.hashes.sha256) == Some(&old) {
+                state.by_sha256.insert(entry.hashes.sha256.clone(), new.clone());
+            }
+            state.data.files.insert(new, entry);
+            state.unsaved += 1;
+        }
+    }
+
+    /// Write the manifest files for everything recorded so far
+    pub fn save(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if self.format.json() {
+            let tmp = self.root.join(format!("{}.tmp", MANIFEST_FILE));
+            fs::write(&tmp, serde_json::to_string_pretty(&state.data)?)?;
+            fs::rename(&tmp, self.root.join(MANIFEST_FILE))?;
+        }
+        if self.format.sums() {
+            for dir in std::mem::take(&mut state.dirty) {
+                write_sums(&self.root, &dir, &state.data)?;
+            }
+        }
+        state.unsaved = 0;
+        Ok(())
+    }
+
+    pub fn save_logged(&self) {
+        if let Err(e) = self.save() {
+            eprintln!("[!] Failed to write the hash manifest: {}", e);
+        }
+    }
+
+    /// Save every SAVE_SECS while there are unsaved entries, so a crash loses
+    /// at most that much of the run
+    pub fn autosave(&'static self) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(std::time::Duration::from_secs(SAVE_SECS)).await;
+                if self.state.lock().unwrap().unsaved > 0 {
+                    tokio::task::spawn_blocking(move || self.save_logged()).await.ok();
+                }
+            }
+        });
+    }
+}
+
+/// Rewrite `<dir>/SHA256SUMS` from the manifest entries directly inside `dir`
+fn write_sums(root: &Path, dir: &str, data: &ManifestData) -> io::Result<()> {
+    let prefix = if dir.is_empty() { String::new() } else { format!("{}/", dir) };
+    let mut sums: BTreeMap<String, String> = BTreeMap::new();
+    let path = root.join(dir).join(SUMS_FILE);
+    // keep lines from earlier runs, which are not in the manifest when only SHA256SUMS is written
+    if let Ok(existing) = fs::read_to_string(&path) {
+        for line in existing.lines() {
+            if let Some((hash, name)) = line.split_once("  ") {
+                sums.insert(name.to_string(), hash.to_string());
+            }
+        }
+    }
+    for (key, entry) in data.files.range(prefix.clone()..) {
+        let Some(name) = key.strip_prefix(&prefix) else {
+            break;
+        };
+        if !name.contains('/') {
+            sums.insert(name.to_string(), entry.hashes.sha256.clone());
+        }
+    }
+    if sums.is_empty() {
+        return Ok(());
+    }
+    let out: String = sums.iter().map(|(name, hash)| format!("{}  {}\n", hash, name)).collect();
+    fs::write(path, out)
+}
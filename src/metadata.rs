@@ -0,0 +1,110 @@
+/*
+    File timestamps and metadata sidecars.
+    Completed files get their mtime from the server's Last-Modified header,
+    or the date shown in the directory listing when there is none, and with
+    --meta-sidecar a `<file>.meta.json` describing where the file came from.
+*/
+
+use crate::hash::Hashes;
+use chrono::{NaiveDate, NaiveDateTime, TimeZone, Utc};
+use filetime::FileTime;
+use reqwest::header::{HeaderMap, LAST_MODIFIED};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::time::SystemTime;
+
+/// Suffix of the sidecar written next to each file
+pub const SIDECAR_SUFFIX: &str = ".meta.json";
+
+/// Write a metadata sidecar next to each completed file
+pub static SIDECARS: AtomicBool = AtomicBool::new(false);
+
+/// Dates shown in directory listings, keyed by file URL
+static LISTING_DATES: Mutex<Option<HashMap<String, SystemTime>>> = Mutex::new(None);
+
+/// Which downloader produced a file
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Downloader {
+    BuiltIn,
+    Aria2,
+}
+
+#[derive(Serialize)]
+struct Sidecar<'a> {
+    url: &'a str,
+    downloader: Downloader,
+    downloaded_at: String,
+    size: u64,
+    #[serde(flatten)]
+    hashes: &'a Hashes,
+    last_modified: Option<String>,
+    headers: BTreeMap<String, String>,
+}
+
+/// Parse a date as shown in a listing: `2023-04-01`, `2023-04-01 13:37` or RFC 3339
+pub fn parse_listing_date(s: &str) -> Option<SystemTime> {
+    let s = s.trim();
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Some(dt.into());
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, format) {
+            return Some(Utc.from_utc_datetime(&dt).into());
+        }
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?).into())
+}
+
+/// Remember the listing date of a file for when the server sends no Last-Modified
+pub fn remember_listing_date(url: &str, date: &str) {
+    if let Some(time) = parse_listing_date(date) {
+        LISTING_DATES.lock().unwrap().get_or_insert_with(HashMap::new).insert(url.to_string(), time);
+    }
+}
+
+/// Listing date remembered for a file URL
+pub fn listing_date(url: &str) -> Option<SystemTime> {
+    LISTING_DATES.lock().unwrap().as_ref().and_then(|m| m.get(url).copied())
+}
+
+pub fn last_modified(headers: &HeaderMap) -> Option<SystemTime> {
+    let value = headers.get(LAST_MODIFIED)?.to_str().ok()?;
+    httpdate::parse_http_date(value).ok()
+}
+
+/// Set the mtime of a completed file and write its sidecar when enabled
+pub fn apply(file_path: &str, url: &str, headers: &HeaderMap, downloader: Downloader, size: u64, hashes: &Hashes) {
+    if let Some(time) = last_modified(headers).or_else(|| listing_date(url)) {
+        // set_file_mtime follows symlinks, so a content-addressed view updates the stored object
+        if let Err(e) = filetime::set_file_mtime(file_path, FileTime::from_system_time(time)) {
+            eprintln!("[!] Failed to set the timestamp of {}: {}", file_path, e);
+        }
+    }
+    if !SIDECARS.load(std::sync::atomic::Ordering::Relaxed) {
+        return;
+    }
+    let sidecar = Sidecar {
+        url,
+        downloader,
+        downloaded_at: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        size,
+        hashes,
+        last_modified: last_modified(headers).map(httpdate::fmt_http_date),
+        headers: headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+            .collect(),
+    };
+    let path = format!("{}{}", file_path, SIDECAR_SUFFIX);
+    let written = serde_json::to_string_pretty(&sidecar)
+        .map_err(std::io::Error::from)
+        .and_then(|json| std::fs::write(Path::new(&path), json));
+    if let Err(e) = written {
+        eprintln!("[!] Failed to write {}: {}", path, e);
+    }
+}
@@ -0,0 +1,316 @@
+/*
+    Client pool with proxy rotation and local address binding.
+    Each configured proxy and/or local source address gets its own reqwest::Client.
+    Requests go through the current route; rate-limit and network errors rotate
+    to the next healthy one, and routes that get 429s sit out a cool-down.
+*/
+
+use crate::error::Error as CrawlError;
+use crate::http::{self, HttpConfig, build_client};
+use reqwest::{Client, StatusCode};
+use std::error::Error;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::time::{Duration, Instant, sleep};
+
+/// Consecutive failures after which a route is marked unhealthy
+const MAX_CONSECUTIVE_FAILURES: u64 = 3;
+/// Seconds between background health checks of unhealthy routes
+const HEALTH_CHECK_INTERVAL_SECS: u64 = 60;
+
+/// Seconds a route sits out after receiving HTTP 429
+pub static ROUTE_COOLDOWN_SECS: AtomicU64 = AtomicU64::new(300);
+
+/// How requests are spread over the routes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Stick to one route and move on only when it fails
+    Failover,
+    /// Use every usable route in turn
+    RoundRobin,
+}
+
+impl Strategy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "failover" => Some(Strategy::Failover),
+            "round-robin" => Some(Strategy::RoundRobin),
+            _ => None,
+        }
+    }
+}
+
+/// One way out to the internet: a direct connection, a proxy, a local address, or both
+pub struct Route {
+    pub label: String,
+    pub proxy: Option<String>,
+    pub bind: Option<IpAddr>,
+    pub client: Client,
+    healthy: AtomicBool,
+    cooldown_until: Mutex<Option<Instant>>,
+    consecutive_failures: AtomicU64,
+    successes: AtomicU64,
+    failures: AtomicU64,
+    rate_limits: AtomicU64,
+}
+
+impl Route {
+    fn new(proxy: Option<String>, bind: Option<IpAddr>, client: Client) -> Self {
+        let label = match (&proxy, &bind) {
+            (None, None) => "direct".to_string(),
+            (Some(p), None) => p.clone(),
+            (None, Some(b)) => b.to_string(),
+            (Some(p), Some(b)) => format!("{} via {}", p, b),
+        };
+        Self {
+            label,
+            proxy,
+            bind,
+            client,
+            healthy: AtomicBool::new(true),
+            cooldown_until: Mutex::new(None),
+            consecutive_failures: AtomicU64::new(0),
+            successes: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            rate_limits: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    fn cooling_until(&self) -> Option<Instant> {
+        let mut until = self.cooldown_until.lock().unwrap();
+        match *until {
+            Some(t) if t > Instant::now() => Some(t),
+            Some(_) => {
+                *until = None;
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn is_usable(&self) -> bool {
+        self.is_healthy() && self.cooling_until().is_none()
+    }
+
+    /// Proxy URL usable by aria2c (`--all-proxy`), which only speaks HTTP proxies
+    pub fn aria_proxy(&self) -> Option<&str> {
+        self.proxy.as_deref().filter(|p| p.starts_with("http://") || p.starts_with("https://"))
+    }
+
+    /// Whether aria2c can use this route at all
+    pub fn supports_aria(&self) -> bool {
+        self.proxy.is_none() || self.aria_proxy().is_some()
+    }
+}
+
+pub struct ClientPool {
+    routes: Vec<Arc<Route>>,
+    strategy: Strategy,
+    current: AtomicUsize,
+    /// HTTP settings passed on to aria2c
+    aria_args: Vec<String>,
+}
+
+impl ClientPool {
+    /// Build one client per proxy and local address combination,
+    /// or a single direct client when neither is given
+    pub fn new(
+        http: &HttpConfig,
+        proxies: &[String],
+        binds: &[IpAddr],
+        strategy: Strategy,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let proxies: Vec<Option<&String>> = if proxies.is_empty() { vec![None] } else { proxies.iter().map(Some).collect() };
+        let binds: Vec<Option<IpAddr>> = if binds.is_empty() { vec![None] } else { binds.iter().copied().map(Some).collect() };
+        let mut routes = Vec::new();
+        for proxy in &proxies {
+            for bind in &binds {
+                let client = build_client(http, proxy.map(|p| p.as_str()), *bind)?;
+                routes.push(Arc::new(Route::new(proxy.cloned(), *bind, client)));
+            }
+        }
+        Ok(Self { routes, strategy, current: AtomicUsize::new(0), aria_args: http::aria_args(http) })
+    }
+
+    /// aria2c options matching the clients' user agent, headers, cookies and CA bundle
+    pub fn aria_args(&self) -> &[String] {
+        &self.aria_args
+    }
+
+    /// Route that the next request should use
+    pub fn current(&self) -> Arc<Route> {
+        let n = self.routes.len();
+        let start = match self.strategy {
+            Strategy::Failover => self.current.load(Ordering::Relaxed),
+            Strategy::RoundRobin => self.current.fetch_add(1, Ordering::Relaxed),
+        };
+        for step in 0..n {
+            let idx = (start + step) % n;
+            if self.routes[idx].is_usable() {
+                if self.strategy == Strategy::Failover && step > 0 {
+                    eprintln!("[*] Switching to route {}", self.routes[idx].label);
+                    self.current.store(idx, Ordering::Relaxed);
+                }
+                return Arc::clone(&self.routes[idx]);
+            }
+        }
+        if self.routes.iter().all(|r| !r.is_healthy()) {
+            eprintln!("[!] No healthy routes left, giving every route another chance");
+            for route in &self.routes {
+                route.healthy.store(true, Ordering::Relaxed);
+                route.consecutive_failures.store(0, Ordering::Relaxed);
+            }
+        }
+        // everything is cooling down: use the route that comes back first
+        let idx = (0..n)
+            .min_by_key(|&idx| self.routes[idx].cooling_until())
+            .unwrap_or(start % n);
+        Arc::clone(&self.routes[idx])
+    }
+
+    pub fn success(&self, route: &Route) {
+        route.successes.fetch_add(1, Ordering::Relaxed);
+        route.consecutive_failures.store(0, Ordering::Relaxed);
+        route.healthy.store(true, Ordering::Relaxed);
+    }
+
+    /// Record a network failure; repeated failures take the route out of use
+    pub fn failure(&self, route: &Route) {
+        route.failures.fetch_add(1, Ordering::Relaxed);
+        let consecutive = route.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if consecutive >= MAX_CONSECUTIVE_FAILURES && route.healthy.swap(false, Ordering::Relaxed) {
+            eprintln!("[!] Route {} marked unhealthy after {} consecutive failures", route.label, consecutive);
+        }
+        self.advance_from(route);
+    }
+
+    /// Record a rate-limit response and put the route on cool-down
+    pub fn rate_limited(&self, route: &Route) {
+        route.failures.fetch_add(1, Ordering::Relaxed);
+        route.rate_limits.fetch_add(1, Ordering::Relaxed);
+        let secs = ROUTE_COOLDOWN_SECS.load(Ordering::Relaxed);
+        if self.routes.len() > 1 && secs > 0 {
+            *route.cooldown_until.lock().unwrap() = Some(Instant::now() + Duration::from_secs(secs));
+            eprintln!("[!] Route {} rate-limited, cooling down for {}s", route.label, secs);
+        }
+        self.advance_from(route);
+    }
+
+    /// In failover mode, move on from a failing route unless someone already did
+    fn advance_from(&self, route: &Route) {
+        if self.strategy != Strategy::Failover || self.routes.len() < 2 {
+            return;
+        }
+        let idx = self.routes.iter().position(|r| std::ptr::eq(r.as_ref(), route)).unwrap_or(0);
+        let _ = self.current.compare_exchange(
+            idx,
+            (idx + 1) % self.routes.len(),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Probe every route (or only the unhealthy ones) against the given URL
+    pub async fn health_check(&self, url: &str, only_unhealthy: bool) {
+        for route in &self.routes {
+            if only_unhealthy && route.is_healthy() {
+                continue;
+            }
+            let ok = match route.client.head(url).timeout(Duration::from_secs(15)).send().await {
+                Ok(resp) => !is_rate_limit_status(resp.status()),
+                Err(_) => false,
+            };
+            let was_healthy = route.healthy.swap(ok, Ordering::Relaxed);
+            if ok {
+                route.consecutive_failures.store(0, Ordering::Relaxed);
+            }
+            if !only_unhealthy || ok != was_healthy {
+                println!("[*] Health check {}: {}", route.label, if ok { "healthy" } else { "unhealthy" });
+            }
+        }
+    }
+
+    /// Periodically re-check unhealthy routes so they can rejoin the rotation
+    pub fn spawn_health_checks(self: &Arc<Self>, url: String) {
+        if self.routes.len() < 2 {
+            return;
+        }
+        let pool = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                sleep(Duration::from_secs(HEALTH_CHECK_INTERVAL_SECS)).await;
+                pool.health_check(&url, true).await;
+            }
+        });
+    }
+
+    pub fn print_stats(&self) {
+        if self.routes.len() < 2 && self.routes[0].proxy.is_none() && self.routes[0].bind.is_none() {
+            return;
+        }
+        println!("=== Route Statistics ===");
+        for route in &self.routes {
+            println!(
+                "{}: {} ok / {} failed / {} rate-limited ({})",
+                route.label,
+                route.successes.load(Ordering::Relaxed),
+                route.failures.load(Ordering::Relaxed),
+                route.rate_limits.load(Ordering::Relaxed),
+                if route.is_healthy() { "healthy" } else { "unhealthy" }
+            );
+        }
+    }
+}
+
+/// Statuses that mean the server is throttling us
+pub fn is_rate_limit_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::SERVICE_UNAVAILABLE
+        || status == StatusCode::FORBIDDEN
+}
+
+/// Errors that should count against a route and trigger rotation
+pub fn is_rotation_error(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => is_rate_limit_status(status),
+        None => e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+    }
+}
+
+/// Report a failed request against its route: 429s cool the route down,
+/// other throttling and network errors count towards marking it unhealthy
+pub fn report_error(pool: &ClientPool, route: &Route, e: &reqwest::Error) {
+    if e.status() == Some(StatusCode::TOO_MANY_REQUESTS) {
+        pool.rate_limited(route);
+    } else if is_rotation_error(e) {
+        pool.failure(route);
+    }
+}
+
+/// Report a failed download against its route, like report_error, and count
+/// rejected bodies too since interstitials usually mean the route is throttled
+pub fn report(pool: &ClientPool, route: &Route, e: &CrawlError) {
+    match e {
+        CrawlError::Network(e) => report_error(pool, route, e),
+        CrawlError::HttpStatus { status, .. } if *status == StatusCode::TOO_MANY_REQUESTS => pool.rate_limited(route),
+        CrawlError::HttpStatus { status, .. } if is_rate_limit_status(*status) => pool.failure(route),
+        CrawlError::Validation(_) => pool.failure(route),
+        _ => {}
+    }
+}
+
+/// Read proxy URLs from a file, one per line; blank lines and `#` comments are ignored
+pub fn read_proxy_file(path: &str) -> std::io::Result<Vec<String>> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
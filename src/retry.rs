@@ -0,0 +1,121 @@
+/*
+    Deferred retry queue.
+    Failed pages and files are parked here instead of blocking the crawl,
+    retried after a cool-down at the end of the run, and whatever still
+    fails is written to a persistent failures list.
+*/
+
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Name of the persistent failures list inside the output directory
+pub const FAILURES_FILE: &str = "failed_downloads.tsv";
+
+/// Seconds to wait before each deferred retry round
+pub static RETRY_COOLDOWN_SECS: AtomicU64 = AtomicU64::new(30);
+/// Number of deferred retry rounds at the end of the run
+pub static RETRY_ROUNDS: AtomicU64 = AtomicU64::new(2);
+
+/// Work item that failed during the crawl
+#[derive(Clone, Debug)]
+pub enum Deferred {
+    /// A directory listing page that could not be fetched
+    Page { url: String, dir: String },
+    /// A file that could not be downloaded
+    File { name: String, url: String, file_path: String, dir: String },
+}
+
+impl Deferred {
+    pub fn url(&self) -> &str {
+        match self {
+            Deferred::Page { url, .. } | Deferred::File { url, .. } => url,
+        }
+    }
+}
+
+static QUEUE: Mutex<Vec<Deferred>> = Mutex::new(Vec::new());
+
+/// Park a failed item until the next retry round
+pub fn defer(item: Deferred) {
+    let mut queue = QUEUE.lock().unwrap();
+    if !queue.iter().any(|q| q.url() == item.url()) {
+        queue.push(item);
+    }
+}
+
+/// Take every queued item, leaving the queue empty
+pub fn take_all() -> Vec<Deferred> {
+    std::mem::take(&mut *QUEUE.lock().unwrap())
+}
+
+/// Number of items currently waiting for a retry
+pub fn pending() -> usize {
+    QUEUE.lock().unwrap().len()
+}
+
+/// Queue the entries of a previous run's failures list so they are retried this run
+pub fn load_failures(root_dir: &str) -> usize {
+    let path = Path::new(root_dir).join(FAILURES_FILE);
+    let content = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(_) => return 0,
+    };
+    let mut count = 0;
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let item = match fields.as_slice() {
+            ["page", url, dir] => Deferred::Page { url: url.to_string(), dir: dir.to_string() },
+            ["file", url, file_path] => {
+                let path = Path::new(file_path);
+                let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+                let dir = path.parent().and_then(|d| d.to_str()).unwrap_or("").to_string();
+                Deferred::File { name, url: url.to_string(), file_path: file_path.to_string(), dir }
+            }
+            _ => {
+                eprintln!("[!] Ignoring malformed line in {}: {}", FAILURES_FILE, line);
+                continue;
+            }
+        };
+        defer(item);
+        count += 1;
+    }
+    count
+}
+
+/// Overwrite the failures list with the items that are still failing
+pub fn save_failures(root_dir: &str, items: &[Deferred]) -> std::io::Result<()> {
+    let path = Path::new(root_dir).join(FAILURES_FILE);
+    if items.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        return Ok(());
+    }
+    let mut out = String::new();
+    for item in items {
+        match item {
+            Deferred::Page { url, dir } => out.push_str(&format!("page\t{}\t{}\n", url, dir)),
+            Deferred::File { url, file_path, .. } => out.push_str(&format!("file\t{}\t{}\n", url, file_path)),
+        }
+    }
+    fs::write(&path, out)
+}
+
+/// Count down the cool-down period before a retry round
+pub async fn cooldown(round: u64, rounds: u64, items: usize) {
+    let secs = RETRY_COOLDOWN_SECS.load(Ordering::Relaxed);
+    println!("[*] Retry round {}/{}: {} deferred items", round, rounds, items);
+    for remaining in (1..=secs).rev() {
+        if crate::shutdown::requested() {
+            eprintln!();
+            return;
+        }
+        eprint!("\r[*] Retrying in {} seconds...", remaining);
+        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    }
+    if secs > 0 {
+        eprintln!("\r[*] Resuming now...          ");
+    }
+}
@@ -0,0 +1,276 @@
+/*
+    Cross-platform file name sanitization.
+    Remote names are turned into local names that are valid on Windows,
+    macOS and Linux: Unicode is normalized to NFC, characters Windows forbids
+    and control characters become `_`, trailing dots and spaces are dropped,
+    reserved device names (`CON`, `NUL`, `COM1`, ...) get a `_` prefix and
+    long names are shortened. Names that end up equal, ignoring case, are
+    told apart with a tag derived from the remote name, so the result does
+    not depend on listing order. Renamed entries are recorded in
+    `name-map.tsv` in the output directory, except for entries that
+    --estimate or --budget only measured.
+*/
+
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use unicode_normalization::UnicodeNormalization;
+
+/// Remote-to-local name mapping kept in the output directory
+pub const NAMES_FILE: &str = "name-map.tsv";
+
+/// Longest local name in bytes. Filesystems allow 255; the rest is room for
+/// `.part`, `.aria2`, `.meta.json`, `.malware` and similar suffixes. Longer
+/// full paths are fine on Windows, where std adds the `\\?\` prefix itself.
+const MAX_NAME_BYTES: usize = 200;
+/// Extensions longer than this are treated as part of the name when shortening
+const MAX_EXTENSION_BYTES: usize = 16;
+
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+static MAP: OnceLock<NameMap> = OnceLock::new();
+/// Entries seen while measuring (--estimate, --budget), written only once chosen for download
+static PENDING: Mutex<Vec<(String, String, String)>> = Mutex::new(Vec::new());
+
+struct NameMap {
+    path: PathBuf,
+    /// Local path recorded for each remote URL
+    recorded: Mutex<HashMap<String, String>>,
+}
+
+/// Short tag identifying a remote name
+fn tag(original: &str) -> String {
+    hex::encode(&Sha256::digest(original.as_bytes())[..4])
+}
+
+/// Split off an extension worth keeping when a name is changed
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(i) if i > 0 && name.len() - i <= MAX_EXTENSION_BYTES => name.split_at(i),
+        _ => (name, ""),
+    }
+}
+
+/// `name` with `~<tag>` before its extension, shortened to MAX_NAME_BYTES
+fn with_tag(name: &str, tag: &str) -> String {
+    let (stem, ext) = split_extension(name);
+    let mut room = MAX_NAME_BYTES.saturating_sub(ext.len() + tag.len() + 1);
+    while !stem.is_char_boundary(room.min(stem.len())) {
+        room -= 1;
+    }
+    format!("{}~{}{}", &stem[..room.min(stem.len())], tag, ext)
+}
+
+/// Local form of a single remote name
+pub fn sanitize(name: &str) -> String {
+    let normalized: String = name
+        .nfc()
+        .map(|c| if c.is_control() || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+        .collect();
+    // Windows drops trailing dots and spaces, which would make two names one
+    let mut local = normalized.trim_end_matches(['.', ' ']).to_string();
+    if local.is_empty() {
+        local = "_".to_string();
+    }
+    if local.starts_with('.') {
+        local.replace_range(..1, "_");
+    }
+    let device = local.split('.').next().unwrap_or("").trim_end().to_ascii_uppercase();
+    if RESERVED.contains(&device.as_str()) {
+        local.insert(0, '_');
+    }
+    if local.len() > MAX_NAME_BYTES {
+        local = with_tag(&local, &tag(name));
+    }
+    local
+}
+
+/// Local names for the entries of one listing, in the same order. When
+/// several remote names map to one local name (ignoring case), the smallest
+/// of those that needed no change, or else the smallest, keeps it and the
+/// others are tagged.
+pub fn local_names(originals: &[String]) -> Vec<String> {
+    let sanitized: Vec<String> = originals.iter().map(|name| sanitize(name)).collect();
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, local) in sanitized.iter().enumerate() {
+        groups.entry(local.to_lowercase()).or_default().push(i);
+    }
+    let mut result = sanitized.clone();
+    for indices in groups.values() {
+        let Some(keeper) = indices.iter().copied().min_by_key(|&i| (originals[i] != sanitized[i], &originals[i])) else {
+            continue;
+        };
+        for &i in indices {
+            // the same remote name listed twice is one file
+            if originals[i] != originals[keeper] {
+                result[i] = with_tag(&sanitized[i], &tag(&originals[i]));
+            }
+        }
+    }
+    result
+}
+
+/// Start recording renamed entries in `<root_dir>/name-map.tsv`
+pub fn open_map(root_dir: &str) -> io::Result<()> {
+    let path = Path::new(root_dir).join(NAMES_FILE);
+    let mut recorded = HashMap::new();
+    match fs::read_to_string(&path) {
+        Ok(content) => {
+            for line in content.lines() {
+                let mut fields = line.split('\t');
+                if let (Some(url), Some(_), Some(local)) = (fields.next(), fields.next(), fields.next()) {
+                    recorded.insert(url.to_string(), local.to_string());
+                }
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let _ = MAP.set(NameMap { path, recorded: Mutex::new(recorded) });
+    Ok(())
+}
+
+/// Record where a remote entry was saved when its local name differs from the remote one.
+/// While only measuring, nothing is written until `record_pending`.
+pub fn record(url: &str, original: &str, local_path: &str) {
+    if MAP.get().is_none() || local_path.rsplit('/').next() == Some(original) {
+        return;
+    }
+    if crate::estimate::ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
+        PENDING.lock().unwrap().push((url.to_string(), original.to_string(), local_path.to_string()));
+        return;
+    }
+    write_entry(url, original, local_path);
+}
+
+/// Record the entries held back while measuring whose local path is kept by `keep`
+pub fn record_pending(keep: impl Fn(&str) -> bool) {
+    for (url, original, local_path) in std::mem::take(&mut *PENDING.lock().unwrap()) {
+        if keep(&local_path) {
+            write_entry(&url, &original, &local_path);
+        }
+    }
+}
+
+fn write_entry(url: &str, original: &str, local_path: &str) {
+    let Some(map) = MAP.get() else {
+        return;
+    };
+    let mut recorded = map.recorded.lock().unwrap();
+    if recorded.get(url).map(String::as_str) == Some(local_path) {
+        return;
+    }
+    let line = format!("{}\t{}\t{}\n", url, original.replace(['\t', '\n', '\r'], " "), local_path);
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&map.path)
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+    match written {
+        Ok(()) => {
+            recorded.insert(url.to_string(), local_path.to_string());
+        }
+        Err(e) => eprintln!("[!] Failed to write {}: {}", NAMES_FILE, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Local name given to each remote name, whatever the listing order
+    fn mapping(originals: &[&str]) -> HashMap<String, String> {
+        let originals: Vec<String> = originals.iter().map(|s| s.to_string()).collect();
+        originals.iter().cloned().zip(local_names(&originals)).collect()
+    }
+
+    #[test]
+    fn reserved_device_names_get_a_prefix() {
+        assert_eq!(sanitize("CON"), "_CON");
+        assert_eq!(sanitize("con.txt"), "_con.txt");
+        assert_eq!(sanitize("COM1.tar.gz"), "_COM1.tar.gz");
+        assert_eq!(sanitize("lpt9"), "_lpt9");
+        assert_eq!(sanitize("NUL .txt"), "_NUL .txt");
+        assert_eq!(sanitize("CONSOLE.txt"), "CONSOLE.txt");
+        assert_eq!(sanitize("COM10"), "COM10");
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces_are_dropped() {
+        assert_eq!(sanitize("report."), "report");
+        assert_eq!(sanitize("report . ."), "report");
+        assert_eq!(sanitize("report.pdf  "), "report.pdf");
+        assert_eq!(sanitize("..."), "_");
+        assert_eq!(sanitize(" "), "_");
+        // a reserved name hidden behind a trailing dot is still reserved
+        assert_eq!(sanitize("AUX."), "_AUX");
+    }
+
+    #[test]
+    fn forbidden_and_control_characters_are_replaced() {
+        assert_eq!(sanitize("a<b>:c\"d|e?f*g"), "a_b__c_d_e_f_g");
+        assert_eq!(sanitize("a/b\\c"), "a_b_c");
+        assert_eq!(sanitize("tab\there"), "tab_here");
+        assert_eq!(sanitize(".hidden"), "_hidden");
+    }
+
+    #[test]
+    fn names_are_normalized_to_nfc() {
+        assert_eq!(sanitize("cafe\u{301}.txt"), "caf\u{e9}.txt");
+        assert_eq!(sanitize("caf\u{e9}.txt"), "caf\u{e9}.txt");
+        // both spellings would land on one file, so the one that needed no change keeps the name
+        let names = mapping(&["cafe\u{301}.txt", "caf\u{e9}.txt"]);
+        assert_eq!(names["caf\u{e9}.txt"], "caf\u{e9}.txt");
+        assert_ne!(names["cafe\u{301}.txt"], "caf\u{e9}.txt");
+    }
+
+    #[test]
+    fn long_names_are_shortened_keeping_the_extension() {
+        let long = format!("{}.exe", "a".repeat(300));
+        let local = sanitize(&long);
+        assert!(local.len() <= MAX_NAME_BYTES);
+        assert!(local.ends_with(&format!("~{}.exe", tag(&long))));
+
+        // multi-byte characters are never cut in half
+        let wide = format!("{}.pdf", "\u{e9}".repeat(150));
+        let local = sanitize(&wide);
+        assert!(local.len() <= MAX_NAME_BYTES);
+        assert!(local.ends_with(".pdf"));
+
+        // different long names stay different
+        let other = format!("{}b.exe", "a".repeat(300));
+        assert_ne!(sanitize(&other), sanitize(&long));
+        assert_eq!(sanitize(&"a".repeat(MAX_NAME_BYTES)), "a".repeat(MAX_NAME_BYTES));
+    }
+
+    #[test]
+    fn collision_tags_do_not_depend_on_listing_order() {
+        let listings: [&[&str]; 3] = [
+            &["Sample.exe", "sample.exe", "SAMPLE.EXE"],
+            &["a?.txt", "a_.txt"],
+            &["x:y", "x_y", "X_Y"],
+        ];
+        for names in listings {
+            let forward = mapping(names);
+            let reversed: Vec<&str> = names.iter().rev().copied().collect();
+            assert_eq!(forward, mapping(&reversed));
+            let unique: std::collections::HashSet<String> = forward.values().map(|n| n.to_lowercase()).collect();
+            assert_eq!(unique.len(), names.len());
+        }
+        // a name that needed no change wins over one that was sanitized onto it
+        assert_eq!(mapping(&["a?.txt", "a_.txt"])["a_.txt"], "a_.txt");
+        assert_eq!(mapping(&["a_.txt", "a?.txt"])["a?.txt"], format!("a_~{}.txt", tag("a?.txt")));
+    }
+
+    #[test]
+    fn a_name_listed_twice_is_one_file() {
+        let originals = vec!["dup.bin".to_string(), "dup.bin".to_string()];
+        assert_eq!(local_names(&originals), vec!["dup.bin", "dup.bin"]);
+    }
+}
@@ -0,0 +1,163 @@
+/*
+    Graceful shutdown on Ctrl-C / SIGTERM.
+    The first signal stops new work and lets in-flight transfers stop at a
+    resumable point; the second one kills any aria2c children, saves the
+    manifest and exits.
+*/
+
+use std::collections::HashSet;
+use std::process::ExitStatus;
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::process::{Child, Command};
+use tokio::sync::Notify;
+use tokio::time::{Duration, timeout};
+
+/// Exit status used when the run was interrupted
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+/// Seconds aria2c gets to save its control file after SIGTERM
+const ARIA_GRACE_SECS: u64 = 10;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static NOTIFY: Notify = Notify::const_new();
+/// PIDs of running aria2c children
+static CHILDREN: Mutex<Option<HashSet<u32>>> = Mutex::new(None);
+/// Run before exiting on the second signal, to save what would be lost
+static EXIT_HOOK: OnceLock<fn()> = OnceLock::new();
+
+/// Whether a shutdown has been requested
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Resolves once a shutdown has been requested
+pub async fn wait() {
+    let notified = NOTIFY.notified();
+    if requested() {
+        return;
+    }
+    notified.await;
+}
+
+/// Stop the run as if it had been interrupted
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+    NOTIFY.notify_waiters();
+}
+
+/// Set what to save when the second signal exits without finishing up
+pub fn on_exit(hook: fn()) {
+    let _ = EXIT_HOOK.set(hook);
+}
+
+/// Listen for SIGINT/SIGTERM in the background
+pub fn install() {
+    tokio::spawn(async {
+        let mut signals = 0;
+        loop {
+            next_signal().await;
+            signals += 1;
+            if signals == 1 {
+                eprintln!();
+                eprintln!("[!] Interrupt received: finishing up, partial downloads are kept for resuming.");
+                eprintln!("[!] Press Ctrl-C again to exit immediately.");
+                request();
+            } else {
+                eprintln!("[!] Second interrupt received, exiting now.");
+                kill_children();
+                if let Some(hook) = EXIT_HOOK.get() {
+                    hook();
+                }
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn next_signal() {
+    use tokio::signal::unix::{SignalKind, signal};
+    // created once per call; tokio keeps the handler installed across calls
+    let mut term = match signal(SignalKind::terminate()) {
+        Ok(s) => s,
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = term.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn next_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+fn kill_children() {
+    let children = CHILDREN.lock().unwrap();
+    for &pid in children.iter().flatten() {
+        send_signal(pid, true);
+    }
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, force: bool) {
+    let sig = if force { libc::SIGKILL } else { libc::SIGTERM };
+    unsafe {
+        libc::kill(pid as libc::pid_t, sig);
+    }
+}
+
+#[cfg(not(unix))]
+fn send_signal(_pid: u32, _force: bool) {
+    // children are spawned with kill_on_drop and are killed when their handle is dropped
+}
+
+/// How an aria2c child ended
+pub enum ChildEnd {
+    Exited(ExitStatus),
+    /// Stopped because shutdown was requested
+    Interrupted,
+    /// Stopped because `restart` resolved; the control file allows continuing
+    Restart,
+}
+
+/// Ask a child to stop, killing it if it does not within the grace period
+async fn stop_child(child: &mut Child, pid: Option<u32>) {
+    if let Some(pid) = pid {
+        send_signal(pid, false);
+    }
+    if timeout(Duration::from_secs(ARIA_GRACE_SECS), child.wait()).await.is_err() {
+        let _ = child.kill().await;
+    }
+}
+
+/// Run an aria2c command. On shutdown, or once `restart` resolves, the child is
+/// asked to stop (aria2c saves its control file on SIGTERM so the download can
+/// be resumed) and killed if it does not.
+pub async fn run_child(mut cmd: Command, restart: impl Future<Output = ()>) -> std::io::Result<ChildEnd> {
+    cmd.kill_on_drop(true);
+    let mut child = cmd.spawn()?;
+    let pid = child.id();
+    if let Some(pid) = pid {
+        CHILDREN.lock().unwrap().get_or_insert_with(HashSet::new).insert(pid);
+    }
+    let result = tokio::select! {
+        status = child.wait() => status.map(ChildEnd::Exited),
+        _ = wait() => {
+            stop_child(&mut child, pid).await;
+            Ok(ChildEnd::Interrupted)
+        }
+        _ = restart => {
+            stop_child(&mut child, pid).await;
+            Ok(ChildEnd::Restart)
+        }
+    };
+    if let (Some(pid), Some(children)) = (pid, CHILDREN.lock().unwrap().as_mut()) {
+        children.remove(&pid);
+    }
+    result
+}
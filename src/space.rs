@@ -0,0 +1,144 @@
+/*
+    Disk-space preflight.
+    Before each download, and every so often while one runs, the free space
+    on the output volume is checked against the file's size, when known, plus
+    a reserve (--min-free). When it runs short the crawl either waits until
+    space is freed and then continues on its own, or stops cleanly so the
+    run can be resumed (--on-low-space).
+*/
+
+use crate::error::{Error, IoContext};
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tokio::time::{Duration, sleep};
+
+/// Free space to keep on the output volume (--min-free, 0 = no checks)
+pub static MIN_FREE: AtomicU64 = AtomicU64::new(1 << 30);
+/// What to do when space runs short (--on-low-space)
+pub static ACTION: OnceLock<Action> = OnceLock::new();
+/// Bytes written between two checks during a transfer
+pub const CHECK_EVERY_BYTES: u64 = 64 << 20;
+
+const POLL_SECS: u64 = 30;
+static WAITING: AtomicBool = AtomicBool::new(false);
+static UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Pause,
+    Stop,
+}
+
+impl Action {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "pause" => Some(Action::Pause),
+            "stop" => Some(Action::Stop),
+            _ => None,
+        }
+    }
+}
+
+/// Bytes available to this user on the volume holding `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Bytes available to this user on the volume holding `path`
+#[cfg(windows)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    unsafe extern "system" {
+        fn GetDiskFreeSpaceExW(dir: *const u16, available: *mut u64, total: *mut u64, free: *mut u64) -> i32;
+    }
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(std::iter::once(0)).collect();
+    let (mut available, mut total, mut free) = (0u64, 0u64, 0u64);
+    if unsafe { GetDiskFreeSpaceExW(wide.as_ptr(), &mut available, &mut total, &mut free) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn free_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "free space cannot be queried on this platform"))
+}
+
+/// Check that `needed` more bytes fit in `dir` while keeping the reserve.
+/// Returns the shortfall as an error without waiting.
+pub fn check(dir: &str, needed: u64) -> crate::error::Result<()> {
+    let reserve = MIN_FREE.load(Ordering::Relaxed);
+    if reserve == 0 && needed == 0 {
+        return Ok(());
+    }
+    // the file's directory may not exist yet; its nearest existing ancestor is on the same volume
+    let mut probe = Path::new(dir);
+    while !probe.exists() {
+        match probe.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => probe = parent,
+            _ => {
+                probe = Path::new(".");
+                break;
+            }
+        }
+    }
+    let free = match free_space(probe) {
+        Ok(free) => free,
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            if !UNSUPPORTED.swap(true, Ordering::Relaxed) {
+                eprintln!("[!] {}, disk-space checks are disabled", e);
+            }
+            return Ok(());
+        }
+        Err(e) => return Err(e).at(probe),
+    };
+    if free >= needed.saturating_add(reserve) {
+        Ok(())
+    } else {
+        Err(Error::DiskFull { dir: dir.to_string(), free, needed, reserve })
+    }
+}
+
+/// Wait until `needed` bytes plus the reserve fit in `dir`. With
+/// --on-low-space stop, or when shutdown is requested, gives up at once.
+pub async fn wait_for_room(dir: &str, needed: u64) -> crate::error::Result<()> {
+    loop {
+        let shortfall = match check(dir, needed) {
+            Ok(()) => break,
+            Err(e @ Error::DiskFull { .. }) => e,
+            Err(e) => return Err(e),
+        };
+        if ACTION.get().copied().unwrap_or(Action::Pause) == Action::Stop {
+            if !crate::shutdown::requested() {
+                eprintln!("[!] {}. Stopping the run; free up space and run again with --resume.", shortfall);
+                crate::shutdown::request();
+            }
+            return Err(shortfall);
+        }
+        if crate::shutdown::requested() {
+            return Err(Error::Interrupted);
+        }
+        if !WAITING.swap(true, Ordering::Relaxed) {
+            eprintln!("[!] {}. Downloads paused until space is freed (checking every {}s).", shortfall, POLL_SECS);
+        }
+        tokio::select! {
+            _ = sleep(Duration::from_secs(POLL_SECS)) => {}
+            _ = crate::shutdown::wait() => {}
+        }
+    }
+    if WAITING.swap(false, Ordering::Relaxed) {
+        println!("[+] Enough free space again, resuming downloads");
+    }
+    Ok(())
+}
@@ -0,0 +1,97 @@
+/*
+    Run statistics printed as the report at the end of a run.
+*/
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+pub struct RunStats {
+    pub pages_scraped: AtomicUsize,
+    pub files_downloaded: AtomicUsize,
+    pub files_skipped: AtomicUsize,
+    pub bytes_downloaded: AtomicU64,
+    pub deferred: AtomicUsize,
+    pub failed: AtomicUsize,
+    pub stalls: AtomicUsize,
+    pub rejected: AtomicUsize,
+    pub unsafe_names: AtomicUsize,
+    pub deduplicated: AtomicUsize,
+    pub dedup_saved: AtomicU64,
+}
+
+pub static STATS: RunStats = RunStats {
+    pages_scraped: AtomicUsize::new(0),
+    files_downloaded: AtomicUsize::new(0),
+    files_skipped: AtomicUsize::new(0),
+    bytes_downloaded: AtomicU64::new(0),
+    deferred: AtomicUsize::new(0),
+    failed: AtomicUsize::new(0),
+    stalls: AtomicUsize::new(0),
+    rejected: AtomicUsize::new(0),
+    unsafe_names: AtomicUsize::new(0),
+    deduplicated: AtomicUsize::new(0),
+    dedup_saved: AtomicU64::new(0),
+};
+
+impl RunStats {
+    pub fn increment_pages(&self) {
+        self.pages_scraped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_files(&self) {
+        self.files_downloaded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_skipped(&self) {
+        self.files_skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes(&self, bytes: u64) {
+        self.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn increment_deferred(&self) {
+        self.deferred.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_failed(&self, count: usize) {
+        self.failed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn increment_stalls(&self) {
+        self.stalls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_unsafe_names(&self) {
+        self.unsafe_names.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_dedup(&self, bytes: u64) {
+        self.deduplicated.fetch_add(1, Ordering::Relaxed);
+        self.dedup_saved.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn print_report(&self) {
+        println!("=== Run Report ===");
+        println!("Pages scraped: {}", self.pages_scraped.load(Ordering::Relaxed));
+        println!("Files downloaded: {}", self.files_downloaded.load(Ordering::Relaxed));
+        println!("Files skipped (already present): {}", self.files_skipped.load(Ordering::Relaxed));
+        println!("Bytes downloaded: {} MB", self.bytes_downloaded.load(Ordering::Relaxed) / 1_048_576);
+        println!("Items deferred for retry: {}", self.deferred.load(Ordering::Relaxed));
+        println!("Stalled transfers: {}", self.stalls.load(Ordering::Relaxed));
+        println!("Rejected responses (HTML/wrong type): {}", self.rejected.load(Ordering::Relaxed));
+        println!("Unsafe names refused: {}", self.unsafe_names.load(Ordering::Relaxed));
+        println!("Items still failing: {}", self.failed.load(Ordering::Relaxed));
+        let deduplicated = self.deduplicated.load(Ordering::Relaxed);
+        if deduplicated > 0 {
+            println!(
+                "Duplicates linked: {} ({} MB saved)",
+                deduplicated,
+                self.dedup_saved.load(Ordering::Relaxed) / 1_048_576
+            );
+        }
+    }
+}
@@ -0,0 +1,152 @@
+/*
+    Content-addressed storage layout (--layout cas).
+    Completed files are moved to `store/sha256/ab/cd/<hash>` under the output
+    directory, stored once per content, and the usual collection tree becomes
+    a view of relative symlinks into the store. `vxug-scraper rebuild-view`
+    recreates the view from the catalog.
+*/
+
+use crate::catalog::CATALOG_FILE;
+use crate::dedup;
+use crate::error::{self, IoContext};
+use crate::hash;
+use crate::manifest;
+use rusqlite::Connection;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Top-level store directory inside the output directory
+pub const STORE_DIR: &str = "store";
+
+/// Output directory when the content-addressed layout is active
+pub static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Store path of a SHA-256 digest: `<root>/store/sha256/ab/cd/<hash>`
+pub fn object_path(root: &Path, sha256: &str) -> PathBuf {
+    root.join(STORE_DIR).join("sha256").join(&sha256[..2]).join(&sha256[2..4]).join(sha256)
+}
+
+/// Point `view_path` at `object` with a relative symlink, replacing what is there
+pub fn link_view(object: &Path, view_path: &Path) -> io::Result<()> {
+    if let Some(parent) = view_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::symlink_metadata(view_path).is_ok() {
+        fs::remove_file(view_path)?;
+    }
+    let dir = view_path.parent().unwrap_or(Path::new(""));
+    dedup::symlink(&dedup::relative_path(dir, object), view_path)
+}
+
+/// Whether the object at `object` still holds the content its name promises
+fn object_intact(object: &Path, size: u64, sha256: &str) -> bool {
+    fs::metadata(object).is_ok_and(|m| m.is_file() && m.len() == size)
+        && hash::hash_file(object).is_ok_and(|(_, hashes)| hashes.sha256 == sha256)
+}
+
+/// Move a completed file into the store and leave a view symlink in its place.
+/// A stored object that no longer matches its hash is replaced by the new copy.
+/// Returns the store path and whether the content was already stored.
+pub fn ingest(root: &Path, file_path: &Path, size: u64, sha256: &str) -> io::Result<(PathBuf, bool)> {
+    let object = object_path(root, sha256);
+    let existed = object_intact(&object, size, sha256);
+    if existed {
+        fs::remove_file(file_path)?;
+    } else {
+        if fs::symlink_metadata(&object).is_ok() {
+            eprintln!("[!] Store object {} is damaged, replacing it", object.display());
+            // hardened objects are read-only, which blocks replacing them on Windows
+            fs::remove_file(&object)?;
+        }
+        fs::create_dir_all(object.parent().unwrap_or(root))?;
+        fs::rename(file_path, &object)?;
+    }
+    link_view(&object, file_path)?;
+    Ok((object, existed))
+}
+
+fn print_command_help() {
+    println!("Usage:");
+    println!("  vxug-scraper rebuild-view [OPTIONS]");
+    println!();
+    println!("Recreate the symlink view of a --layout cas output directory from the catalog.");
+    println!();
+    println!("Options:");
+    println!("  -o, --output-dir <DIR>         Output directory holding the store (default: Downloads)");
+    println!("  --catalog-file <FILE>          Catalog database to read (default: <DIR>/{})", CATALOG_FILE);
+}
+
+/// Entry point for `vxug-scraper rebuild-view ...`
+pub fn run_command(args: &[String]) -> error::Result<()> {
+    let mut root_dir = String::from("Downloads");
+    let mut catalog_file: Option<String> = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-h" | "--help" => {
+                print_command_help();
+                return Ok(());
+            }
+            "-o" | "--output-dir" | "--catalog-file" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                if args[i] == "--catalog-file" {
+                    catalog_file = Some(args[i + 1].clone());
+                } else {
+                    root_dir = args[i + 1].clone();
+                }
+                i += 2;
+            }
+            other => {
+                eprintln!("Error: unknown rebuild-view option: {}", other);
+                print_command_help();
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let root = PathBuf::from(&root_dir);
+    let path = catalog_file.map(PathBuf::from).unwrap_or_else(|| root.join(CATALOG_FILE));
+    if !path.exists() {
+        eprintln!("Error: no catalog at {}", path.display());
+        std::process::exit(1);
+    }
+    let conn = Connection::open(&path)?;
+    let mut stmt = conn.prepare("SELECT local_path, sha256 FROM files WHERE sha256 IS NOT NULL ORDER BY local_path")?;
+    let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?;
+
+    let (mut linked, mut missing, mut kept) = (0, 0, 0);
+    for row in rows {
+        let (local_path, sha256) = row?;
+        if sha256.len() != 64 || !sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            eprintln!("[!] Ignoring {}: invalid SHA-256 in the catalog", local_path);
+            missing += 1;
+            continue;
+        }
+        let object = object_path(&root, &sha256);
+        if !object.is_file() {
+            eprintln!("[!] {} is not in the store ({})", local_path, sha256);
+            missing += 1;
+            continue;
+        }
+        // paths are recorded as the crawl saw them; re-anchor them on this output directory
+        let view_path = root.join(manifest::relative_key(&root, Path::new(&local_path)));
+        match fs::symlink_metadata(&view_path) {
+            Ok(meta) if !meta.file_type().is_symlink() => {
+                eprintln!("[!] Leaving regular file {} in place", view_path.display());
+                kept += 1;
+                continue;
+            }
+            _ => {}
+        }
+        link_view(&object, &view_path).at(&view_path)?;
+        linked += 1;
+    }
+    println!("[+] View rebuilt: {} links, {} missing from the store, {} regular files left alone", linked, missing, kept);
+    Ok(())
+}
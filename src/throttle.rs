@@ -0,0 +1,286 @@
+/*
+    Global bandwidth cap and time-of-day download windows.
+    Every chunk the built-in downloader receives goes through a shared token
+    bucket whose rate is re-evaluated against the schedule on each call, so a
+    running crawl follows the schedule (and edits to the schedule file) live.
+    Each running aria2c holds an equal share of the same cap, one share stays
+    with the bucket, and aria2c is restarted when its share changes.
+*/
+
+use chrono::{Local, NaiveTime, Timelike};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::SystemTime;
+use tokio::time::{Duration, Instant, sleep};
+
+/// Default cap in bytes per second outside scheduled windows (0 = unlimited)
+pub static LIMIT_RATE: AtomicU64 = AtomicU64::new(0);
+/// Seconds between checks while a pause window is active
+const PAUSE_POLL_SECS: u64 = 30;
+/// Seconds between checks of a running aria2c's share
+const ARIA_CHECK_SECS: u64 = 5;
+
+/// Download speed in effect at a given moment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rate {
+    Unlimited,
+    Limited(u64),
+    Paused,
+}
+
+/// A daily window such as `09:00-12:00=pause`
+#[derive(Clone, Debug)]
+struct Window {
+    start: u32,
+    end: u32,
+    rate: Rate,
+}
+
+impl Window {
+    /// Whether the minute of the day falls in the window; windows with end <= start wrap past midnight
+    fn contains(&self, minute: u32) -> bool {
+        if self.start < self.end {
+            minute >= self.start && minute < self.end
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+struct Schedule {
+    windows: Vec<Window>,
+    file: Option<(String, Option<SystemTime>)>,
+}
+
+static SCHEDULE: Mutex<Schedule> = Mutex::new(Schedule { windows: Vec::new(), file: None });
+
+struct Bucket {
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+static BUCKET: Mutex<Bucket> = Mutex::new(Bucket { tokens: 0.0, last: None });
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Limit each running aria2c was started with, None when unlimited
+static ARIA_HELD: Mutex<BTreeMap<u64, Option<u64>>> = Mutex::new(BTreeMap::new());
+static NEXT_ARIA: AtomicU64 = AtomicU64::new(0);
+
+/// Parse a size such as `512`, `10K`, `2M`, `500G` or `1.5T` (binary units, optional trailing `B`)
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let s = s.strip_suffix(['B', 'b']).unwrap_or(s);
+    let (number, multiplier) = match s.chars().last()? {
+        'K' | 'k' => (&s[..s.len() - 1], 1u64 << 10),
+        'M' | 'm' => (&s[..s.len() - 1], 1u64 << 20),
+        'G' | 'g' => (&s[..s.len() - 1], 1u64 << 30),
+        'T' | 't' => (&s[..s.len() - 1], 1u64 << 40),
+        _ => (s, 1),
+    };
+    let value: f64 = number.trim().parse().ok()?;
+    if value < 0.0 || !value.is_finite() {
+        return None;
+    }
+    Some((value * multiplier as f64) as u64)
+}
+
+fn parse_time(s: &str) -> Option<u32> {
+    let t = NaiveTime::parse_from_str(s.trim(), "%H:%M").ok()?;
+    Some(t.hour() * 60 + t.minute())
+}
+
+/// Parse a schedule such as `22:00-06:00=full,09:00-12:00=pause,12:00-18:00=2M`.
+/// Entries may be separated by commas or newlines; `#` starts a comment.
+fn parse_schedule(spec: &str) -> Result<Vec<Window>, String> {
+    let mut windows = Vec::new();
+    for entry in spec.split([',', '\n']) {
+        let entry = entry.split('#').next().unwrap_or("").trim();
+        if entry.is_empty() {
+            continue;
+        }
+        let (range, action) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("missing '=' in schedule entry: {}", entry))?;
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format!("expected HH:MM-HH:MM in schedule entry: {}", entry))?;
+        let start = parse_time(start).ok_or_else(|| format!("invalid time in schedule entry: {}", entry))?;
+        let end = parse_time(end).ok_or_else(|| format!("invalid time in schedule entry: {}", entry))?;
+        let rate = match action.trim() {
+            "full" | "unlimited" => Rate::Unlimited,
+            "pause" | "paused" => Rate::Paused,
+            other => Rate::Limited(
+                parse_size(other).ok_or_else(|| format!("invalid rate in schedule entry: {}", entry))?,
+            ),
+        };
+        windows.push(Window { start, end, rate });
+    }
+    Ok(windows)
+}
+
+/// Install a schedule given on the command line
+pub fn set_schedule(spec: &str) -> Result<(), String> {
+    let windows = parse_schedule(spec)?;
+    SCHEDULE.lock().unwrap().windows = windows;
+    Ok(())
+}
+
+/// Install a schedule read from a file; the file is re-read whenever it changes
+pub fn set_schedule_file(path: &str) -> Result<(), String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let windows = parse_schedule(&content)?;
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut schedule = SCHEDULE.lock().unwrap();
+    schedule.windows = windows;
+    schedule.file = Some((path.to_string(), modified));
+    Ok(())
+}
+
+fn reload_schedule_file(schedule: &mut Schedule) {
+    let Some((path, modified)) = &schedule.file else {
+        return;
+    };
+    let current = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    if current == *modified {
+        return;
+    }
+    let path = path.clone();
+    match std::fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|c| parse_schedule(&c)) {
+        Ok(windows) => {
+            println!("[*] Reloaded download schedule from {}", path);
+            schedule.windows = windows;
+        }
+        Err(e) => eprintln!("[!] Keeping previous schedule, {} is invalid: {}", path, e),
+    }
+    schedule.file = Some((path, current));
+}
+
+/// Rate in effect right now, taking the schedule into account
+pub fn current_rate() -> Rate {
+    let mut schedule = SCHEDULE.lock().unwrap();
+    reload_schedule_file(&mut schedule);
+    let now = Local::now();
+    let minute = now.hour() * 60 + now.minute();
+    if let Some(window) = schedule.windows.iter().find(|w| w.contains(minute)) {
+        return window.rate;
+    }
+    match LIMIT_RATE.load(Ordering::Relaxed) {
+        0 => Rate::Unlimited,
+        bps => Rate::Limited(bps),
+    }
+}
+
+/// Block while a pause window is active. Returns how long it waited.
+pub async fn wait_if_paused() -> Duration {
+    let started = Instant::now();
+    while current_rate() == Rate::Paused && !crate::shutdown::requested() {
+        if !PAUSED.swap(true, Ordering::Relaxed) {
+            println!("[*] Downloads paused by schedule");
+        }
+        tokio::select! {
+            _ = sleep(Duration::from_secs(PAUSE_POLL_SECS)) => {}
+            _ = crate::shutdown::wait() => {}
+        }
+    }
+    if PAUSED.swap(false, Ordering::Relaxed) {
+        println!("[*] Download window open again, resuming");
+    }
+    started.elapsed()
+}
+
+/// Part of a cap of `bps` the built-in downloader may use: what the aria2c
+/// processes do not hold, and never less than one share
+fn bucket_rate(bps: u64) -> u64 {
+    let held = ARIA_HELD.lock().unwrap();
+    let aria: u64 = held.values().map(|limit| limit.unwrap_or(bps)).sum();
+    bps.saturating_sub(aria).max(bps / (held.len() as u64 + 1)).max(1)
+}
+
+/// Account for `bytes` just received and sleep as needed to honour the cap.
+/// Returns how long it waited, so callers can exclude it from speed measurements.
+pub async fn acquire(bytes: usize) -> Duration {
+    let mut waited = wait_if_paused().await;
+    let bps = match current_rate() {
+        Rate::Limited(bps) if bps > 0 => bucket_rate(bps) as f64,
+        _ => return waited,
+    };
+    let delay = {
+        let mut bucket = BUCKET.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = bucket.last.map(|t| now.duration_since(t).as_secs_f64()).unwrap_or(0.0);
+        // allow at most one second of burst
+        bucket.tokens = (bucket.tokens + elapsed * bps).min(bps);
+        bucket.last = Some(now);
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens < 0.0 { -bucket.tokens / bps } else { 0.0 }
+    };
+    if delay > 0.0 {
+        let delay = Duration::from_secs_f64(delay);
+        sleep(delay).await;
+        waited += delay;
+    }
+    waited
+}
+
+/// A running aria2c's share of the cap, given back when dropped
+pub struct AriaShare {
+    id: u64,
+}
+
+impl AriaShare {
+    pub fn new() -> Self {
+        let id = NEXT_ARIA.fetch_add(1, Ordering::Relaxed);
+        ARIA_HELD.lock().unwrap().insert(id, Some(0));
+        AriaShare { id }
+    }
+
+    /// Rate this process should run at now: an equal share of the cap, less
+    /// whatever the others hold above theirs until they are restarted
+    pub fn target(&self) -> Rate {
+        match current_rate() {
+            Rate::Limited(bps) => {
+                let held = ARIA_HELD.lock().unwrap();
+                let others: u64 =
+                    held.iter().filter(|&(&id, _)| id != self.id).map(|(_, limit)| limit.unwrap_or(bps)).sum();
+                let share = bps / (held.len() as u64 + 1);
+                // aria2c reads a limit of 0 as unlimited
+                Rate::Limited(share.min(bps.saturating_sub(others + share)).max(1))
+            }
+            other => other,
+        }
+    }
+
+    /// Take the target rate; returns the `--max-download-limit` to start aria2c with
+    pub fn claim(&self) -> (Rate, Option<u64>) {
+        let rate = self.target();
+        let limit = match rate {
+            Rate::Limited(limit) => Some(limit),
+            Rate::Unlimited => None,
+            Rate::Paused => Some(1),
+        };
+        ARIA_HELD.lock().unwrap().insert(self.id, limit);
+        (rate, limit)
+    }
+
+    /// Resolves once the target moves away from `claimed`: any drop, since the
+    /// cap must hold, a rise of more than a quarter, or a pause or cap change
+    pub async fn changed(&self, claimed: Rate) {
+        loop {
+            sleep(Duration::from_secs(ARIA_CHECK_SECS)).await;
+            let changed = match (claimed, self.target()) {
+                (Rate::Limited(old), Rate::Limited(new)) => new < old || new > old + old / 4,
+                (old, new) => old != new,
+            };
+            if changed {
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for AriaShare {
+    fn drop(&mut self) {
+        ARIA_HELD.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,107 @@
+/*
+    Payload validation.
+    A 2xx response is not proof of a payload: rate-limit interstitials and
+    error pages come back as HTML. The Content-Type and the leading bytes of
+    each download are checked against the type its extension promises, and
+    bad bodies are moved to `<file>.rejected` for diagnosis.
+*/
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Suffix of bodies kept aside after failing validation
+pub const REJECTED_SUFFIX: &str = ".rejected";
+/// Largest error body kept for diagnosis
+pub const REJECTED_MAX_BYTES: usize = 1 << 20;
+
+/// File types the scraper knows the signature of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Zip,
+    SevenZip,
+    Pdf,
+    Rar,
+}
+
+impl Kind {
+    /// Expected type for a file name, from its extension
+    pub fn from_name(name: &str) -> Option<Self> {
+        let ext = name.rsplit_once('.')?.1.to_ascii_lowercase();
+        match ext.as_str() {
+            "zip" => Some(Kind::Zip),
+            "7z" => Some(Kind::SevenZip),
+            "pdf" => Some(Kind::Pdf),
+            "rar" => Some(Kind::Rar),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Zip => "ZIP",
+            Kind::SevenZip => "7z",
+            Kind::Pdf => "PDF",
+            Kind::Rar => "RAR",
+        }
+    }
+
+    fn matches(self, head: &[u8]) -> bool {
+        match self {
+            // local file header, or the end-of-directory record of an empty archive
+            Kind::Zip => head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06"),
+            Kind::SevenZip => head.starts_with(b"7z\xBC\xAF\x27\x1C"),
+            // the PDF header may be preceded by junk within the first 1024 bytes
+            Kind::Pdf => head.windows(5).any(|w| w == b"%PDF-"),
+            Kind::Rar => head.starts_with(b"Rar!\x1A\x07"),
+        }
+    }
+}
+
+fn looks_like_html(head: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(&head[..head.len().min(512)]).to_ascii_lowercase();
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    text.starts_with("<!doctype html") || text.starts_with("<html") || text.starts_with("<head") || text.contains("<body")
+}
+
+/// Check a response's Content-Type against the file name. Err holds the reason.
+pub fn check_content_type(name: &str, content_type: Option<&str>) -> Result<(), String> {
+    let Some(content_type) = content_type else {
+        return Ok(());
+    };
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    let textual = mime.starts_with("text/") || mime == "application/xhtml+xml" || mime == "application/json";
+    if textual && Kind::from_name(name).is_some() {
+        return Err(format!("server sent {} instead of the file", mime));
+    }
+    Ok(())
+}
+
+/// Check the leading bytes of a file against the type its name promises
+pub fn check_head(name: &str, head: &[u8]) -> Result<(), String> {
+    match Kind::from_name(name) {
+        Some(kind) if !kind.matches(head) => {
+            if looks_like_html(head) {
+                Err("file is an HTML page".to_string())
+            } else {
+                Err(format!("file does not start with a {} signature", kind.label()))
+            }
+        }
+        None if looks_like_html(head) => Err("file is an HTML page".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Check the first KB of a file on disk
+pub fn check_file(name: &str, path: &Path) -> io::Result<Result<(), String>> {
+    let mut head = Vec::with_capacity(1024);
+    File::open(path)?.take(1024).read_to_end(&mut head)?;
+    Ok(check_head(name, &head))
+}
+
+/// Move a rejected body next to the real path as `<file>.rejected`
+pub fn set_aside(bad: &Path, file_path: &str) -> io::Result<String> {
+    let rejected = format!("{}{}", file_path, REJECTED_SUFFIX);
+    fs::rename(bad, &rejected)?;
+    Ok(rejected)
+}
@@ -0,0 +1,332 @@
+/*
+    `vxug-scraper verify`: audit the local mirror against the hash manifest.
+    Walks the output directory, re-hashes every file recorded in the manifest
+    and reports missing, extra, zero-byte, leftover `.aria2`/`.part` and
+    mismatched files. With --requeue the bad ones are queued in the failures
+    list so the next scrape downloads them again.
+*/
+
+use crate::error::{self, IoContext};
+use crate::hash;
+use crate::manifest::{self, FileEntry};
+use crate::retry::{self, Deferred};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Files written by the scraper itself, never reported as extra
+const INTERNAL_FILES: [&str; 9] = [
+    manifest::MANIFEST_FILE,
+    manifest::SUMS_FILE,
+    crate::journal::JOURNAL_FILE,
+    crate::catalog::CATALOG_FILE,
+    retry::FAILURES_FILE,
+    "manifest.json.tmp",
+    crate::harden::MARKER_FILE,
+    crate::sanitize::NAMES_FILE,
+    crate::budget::LEFT_OUT_FILE,
+];
+
+#[derive(Default)]
+struct Report {
+    missing: Vec<String>,
+    extra: Vec<String>,
+    zero_byte: Vec<String>,
+    leftover: Vec<String>,
+    mismatched: Vec<(String, String)>,
+    ok: usize,
+}
+
+impl Report {
+    fn problems(&self) -> usize {
+        self.missing.len() + self.extra.len() + self.zero_byte.len() + self.leftover.len() + self.mismatched.len()
+    }
+}
+
+fn print_command_help() {
+    println!("Usage:");
+    println!("  vxug-scraper verify [OPTIONS]");
+    println!();
+    println!("Options:");
+    println!("  -o, --output-dir <DIR>         Mirror to verify (default: Downloads)");
+    println!("  --size-only                    Compare sizes only, without re-hashing");
+    println!("  -j, --jobs <NUM>               Files hashed in parallel (default: 4)");
+    println!("  --requeue                      Queue missing and bad files for the next scrape");
+}
+
+/// Files the scraper keeps for itself next to the mirrored ones
+pub fn is_internal(name: &str) -> bool {
+    INTERNAL_FILES.contains(&name)
+        || name.starts_with(&format!("{}-", crate::catalog::CATALOG_FILE))
+        || name.ends_with(crate::metadata::SIDECAR_SUFFIX)
+        // bodies kept for diagnosis after failing validation; the file itself is retried
+        || name.ends_with(crate::validate::REJECTED_SUFFIX)
+}
+
+/// Collect every regular file under `dir` as a manifest key
+fn walk(root: &Path, dir: &Path, out: &mut Vec<(String, u64)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        eprintln!("[!] Cannot read directory {}", dir.display());
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            // objects of the content-addressed layout are checked through the view
+            if dir == root && entry.file_name() == crate::store::STORE_DIR {
+                continue;
+            }
+            walk(root, &path, out);
+            continue;
+        }
+        // links to files, such as deduplicated copies, are checked like regular
+        // files; links to directories are not followed, so a loop cannot recurse
+        let Ok(meta) = fs::metadata(&path) else {
+            continue;
+        };
+        if meta.is_file() {
+            out.push((manifest::relative_key(root, &path), meta.len()));
+        }
+    }
+}
+
+/// Expected digests: the JSON manifest, or SHA256SUMS files when there is none
+fn expected_files(root: &Path, on_disk: &[(String, u64)]) -> error::Result<BTreeMap<String, FileEntry>> {
+    let data = manifest::load(root).at(root.join(manifest::MANIFEST_FILE))?;
+    if !data.files.is_empty() {
+        return Ok(data.files);
+    }
+    let mut files = BTreeMap::new();
+    for (key, _) in on_disk.iter().filter(|(k, _)| k.rsplit('/').next() == Some(manifest::SUMS_FILE)) {
+        let dir = key.strip_suffix(manifest::SUMS_FILE).unwrap_or("");
+        let content = fs::read_to_string(root.join(key)).at(root.join(key))?;
+        for line in content.lines() {
+            if let Some((sha256, name)) = line.split_once("  ") {
+                let entry = FileEntry {
+                    url: String::new(),
+                    size: 0,
+                    hashes: hash::Hashes { sha256: sha256.to_string(), ..Default::default() },
+                    recorded_at: String::new(),
+                    archive: None,
+                };
+                files.insert(format!("{}{}", dir, name), entry);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Entry point for `vxug-scraper verify ...`. Returns the number of problems found.
+pub fn run_command(args: &[String]) -> error::Result<usize> {
+    let mut root_dir = String::from("Downloads");
+    let mut size_only = false;
+    let mut requeue = false;
+    let mut jobs = 4usize;
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-h" | "--help" => {
+                print_command_help();
+                return Ok(0);
+            }
+            "-o" | "--output-dir" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                root_dir = args[i + 1].clone();
+                i += 2;
+            }
+            "-j" | "--jobs" => {
+                if i + 1 >= args.len() {
+                    eprintln!("Error: {} requires a value", args[i]);
+                    std::process::exit(1);
+                }
+                jobs = args[i + 1].parse::<usize>().ok().filter(|&j| j > 0).unwrap_or_else(|| {
+                    eprintln!("Error: invalid jobs value: {}", args[i + 1]);
+                    std::process::exit(1);
+                });
+                i += 2;
+            }
+            "--size-only" => {
+                size_only = true;
+                i += 1;
+            }
+            "--requeue" => {
+                requeue = true;
+                i += 1;
+            }
+            other => {
+                eprintln!("Error: unknown verify option: {}", other);
+                print_command_help();
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let root = PathBuf::from(&root_dir);
+    if !root.is_dir() {
+        eprintln!("Error: {} is not a directory", root_dir);
+        std::process::exit(1);
+    }
+    println!("[*] Scanning {}...", root_dir);
+    let mut on_disk = Vec::new();
+    walk(&root, &root, &mut on_disk);
+    let expected = expected_files(&root, &on_disk)?;
+    if expected.is_empty() {
+        eprintln!("[!] No {} or {} files found in {}, nothing to verify against", manifest::MANIFEST_FILE, manifest::SUMS_FILE, root_dir);
+    }
+    println!("[*] {} files on disk, {} recorded in the manifest", on_disk.len(), expected.len());
+
+    let mut report = Report::default();
+    let present: BTreeSet<&str> = on_disk.iter().map(|(k, _)| k.as_str()).collect();
+    let mut to_check = Vec::new();
+    for (key, size) in &on_disk {
+        let name = key.rsplit('/').next().unwrap_or(key);
+        if is_internal(name) {
+            continue;
+        }
+        if name.ends_with(".aria2") || name.ends_with(".part") {
+            report.leftover.push(key.clone());
+        } else if *size == 0 {
+            report.zero_byte.push(key.clone());
+        } else if let Some(entry) = expected.get(key) {
+            to_check.push((key.clone(), *size, entry));
+        } else {
+            report.extra.push(key.clone());
+        }
+    }
+    for key in expected.keys() {
+        if !present.contains(key.as_str()) {
+            report.missing.push(key.clone());
+        }
+    }
+
+    // re-hash in parallel; sizes are compared first so truncated files fail fast
+    let total = to_check.len();
+    let done = AtomicUsize::new(0);
+    let queue = Mutex::new(to_check.into_iter());
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                loop {
+                    let Some((key, size, entry)) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    let problem = if entry.size > 0 && entry.size != size {
+                        Some(format!("size {} != recorded {}", size, entry.size))
+                    } else if size_only {
+                        None
+                    } else {
+                        match hash::hash_file(&root.join(&key)) {
+                            Ok((_, hashes)) if hashes.sha256 == entry.hashes.sha256 => None,
+                            Ok((_, hashes)) => Some(format!("sha256 {} != recorded {}", hashes.sha256, entry.hashes.sha256)),
+                            Err(e) => Some(format!("cannot read: {}", e)),
+                        }
+                    };
+                    results.lock().unwrap().push((key, problem));
+                    let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    if n.is_multiple_of(100) || n == total {
+                        eprint!("\r[*] Checked {}/{} files", n, total);
+                    }
+                }
+            });
+        }
+    });
+    if total > 0 {
+        eprintln!();
+    }
+    for (key, problem) in results.into_inner().unwrap() {
+        match problem {
+            Some(reason) => report.mismatched.push((key, reason)),
+            None => report.ok += 1,
+        }
+    }
+    report.mismatched.sort();
+
+    print_report(&report);
+    if requeue {
+        requeue_bad(&root_dir, &root, &expected, &report)?;
+    }
+    Ok(report.problems())
+}
+
+fn print_report(report: &Report) {
+    for key in &report.missing {
+        println!("MISSING   {}", key);
+    }
+    for (key, reason) in &report.mismatched {
+        println!("MISMATCH  {} ({})", key, reason);
+    }
+    for key in &report.zero_byte {
+        println!("EMPTY     {}", key);
+    }
+    for key in &report.leftover {
+        println!("LEFTOVER  {}", key);
+    }
+    for key in &report.extra {
+        println!("EXTRA     {}", key);
+    }
+    println!("=== Verify Report ===");
+    println!("Verified OK: {}", report.ok);
+    println!("Missing: {}", report.missing.len());
+    println!("Hash/size mismatch: {}", report.mismatched.len());
+    println!("Zero-byte: {}", report.zero_byte.len());
+    println!("Leftover .aria2/.part: {}", report.leftover.len());
+    println!("Extra (not in manifest): {}", report.extra.len());
+}
+
+/// Store object a view symlink points at, if `path` is one
+fn store_object(root: &Path, path: &Path) -> Option<PathBuf> {
+    if !fs::symlink_metadata(path).ok()?.file_type().is_symlink() {
+        return None;
+    }
+    let target = fs::canonicalize(path).ok()?;
+    let store = fs::canonicalize(root.join(crate::store::STORE_DIR)).ok()?;
+    target.starts_with(store).then_some(target)
+}
+
+/// Queue missing, mismatched and zero-byte files in the failures list. Bad copies
+/// are removed first, since the scraper skips files that already exist.
+fn requeue_bad(root_dir: &str, root: &Path, expected: &BTreeMap<String, FileEntry>, report: &Report) -> error::Result<()> {
+    retry::load_failures(root_dir);
+    let mut queued = 0;
+    let mut no_url = 0;
+    let bad = report
+        .missing
+        .iter()
+        .chain(report.mismatched.iter().map(|(k, _)| k))
+        .chain(report.zero_byte.iter());
+    for key in bad {
+        let Some(entry) = expected.get(key).filter(|e| !e.url.is_empty()) else {
+            no_url += 1;
+            continue;
+        };
+        let path = root.join(key);
+        // in the content-addressed layout the bad data is the store object behind the view
+        if let Some(object) = store_object(root, &path) {
+            fs::remove_file(&object).at(&object)?;
+        }
+        if fs::symlink_metadata(&path).is_ok() {
+            fs::remove_file(&path).at(&path)?;
+        }
+        let file_path = path.to_string_lossy().into_owned();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+        let dir = path.parent().and_then(|d| d.to_str()).unwrap_or("").to_string();
+        retry::defer(Deferred::File { name, url: entry.url.clone(), file_path, dir });
+        queued += 1;
+    }
+    retry::save_failures(root_dir, &retry::take_all()).at(root.join(retry::FAILURES_FILE))?;
+    println!("[+] Queued {} files in {}/{} for the next scrape", queued, root_dir, retry::FAILURES_FILE);
+    if no_url > 0 {
+        eprintln!("[!] {} bad files have no recorded URL and were not queued", no_url);
+    }
+    Ok(())
+}